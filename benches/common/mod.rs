@@ -0,0 +1,16 @@
+// Input shared by the benchmarks.
+
+pub const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+// Text-like input: a skewed distribution over ~60 distinct bytes. `state` carries the xorshift
+// generator over to the next chunk.
+pub fn fill(chunk: &mut [u8], state: &mut u64) {
+    for byte in chunk {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+
+        let roll = (*state % 1000) as u32;
+        *byte = b' ' + (roll * roll / 16_000) as u8;
+    }
+}
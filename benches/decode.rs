@@ -0,0 +1,57 @@
+use huffman_coding::{compressor, decompressor};
+use std::time::Instant;
+
+mod common;
+
+const MESSAGE_SIZE: usize = 8 * 1024 * 1024;
+const ROUNDS: u32 = 5;
+
+fn message() -> Vec<u8> {
+    let mut message = vec![0u8; MESSAGE_SIZE];
+    let mut state = common::SEED;
+    common::fill(&mut message, &mut state);
+
+    message
+}
+
+fn throughput(name: &str, decode: impl Fn() -> Vec<u8>) {
+    let started = Instant::now();
+
+    for _ in 0..ROUNDS {
+        assert_eq!(decode().len(), MESSAGE_SIZE);
+    }
+
+    let seconds = started.elapsed().as_secs_f64();
+    let megabytes = (MESSAGE_SIZE as f64 * ROUNDS as f64) / (1024.0 * 1024.0);
+
+    println!("{:>12}: {:8.1} MiB/s", name, megabytes / seconds);
+}
+
+fn main() {
+    let message = message();
+
+    let mut frequencies = [0u64; 256];
+    compressor::histogram::count(&message, &mut frequencies);
+
+    let huffman_tree =
+        compressor::tree::build(compressor::tree::from_frequencies(&frequencies)).unwrap();
+    let codes = compressor::calc_codes(&huffman_tree).unwrap();
+    let compressed = compressor::compress(&message, &codes).unwrap();
+
+    let decoding_tree = compressor::tree::from_codes(&codes).unwrap().unwrap();
+    let table = decompressor::table::DecodeTable::new(&codes);
+
+    println!(
+        "Decoding {} MiB compressed into {} MiB, {} rounds",
+        MESSAGE_SIZE >> 20,
+        compressed.0.len() >> 20,
+        ROUNDS
+    );
+
+    throughput("tree walker", || {
+        decompressor::decompress(&compressed, &decoding_tree).unwrap()
+    });
+    throughput("table", || {
+        decompressor::table::decompress(&compressed, &table).unwrap()
+    });
+}
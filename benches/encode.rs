@@ -0,0 +1,83 @@
+use huffman_coding::{compressor, HuffmanCode};
+use std::collections::HashMap;
+use std::time::Instant;
+
+mod common;
+
+const MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+const ROUNDS: u32 = 5;
+
+fn message() -> Vec<u8> {
+    let mut message = vec![0u8; MESSAGE_SIZE];
+    let mut state = common::SEED;
+    common::fill(&mut message, &mut state);
+
+    message
+}
+
+// The byte-at-a-time packing the encoder used before `BitWriter`.
+fn compress_bytewise(bytes: &[u8], codes: &HashMap<u8, HuffmanCode>) -> (Vec<u8>, usize) {
+    let mut result = Vec::<u8>::new();
+    let mut total_length = 0usize;
+
+    for byte in bytes {
+        let code = &codes[byte];
+        let mut pending_bits = code.bin_length();
+
+        while pending_bits > 0 {
+            let mut free_bits = (result.len() * 8 - total_length) as u8;
+
+            if free_bits == 0 {
+                result.push(0);
+                free_bits = 8;
+            }
+
+            let taken_bits = std::cmp::min(free_bits, pending_bits);
+            let mask = (1u64 << taken_bits) - 1;
+            let tail_filler = ((code.bin_repres() >> (pending_bits - taken_bits)) & mask) as u8;
+
+            *result.last_mut().unwrap() |= tail_filler << (free_bits - taken_bits);
+
+            pending_bits -= taken_bits;
+            total_length += taken_bits as usize;
+        }
+    }
+
+    (result, total_length)
+}
+
+fn throughput(name: &str, encode: impl Fn() -> (Vec<u8>, usize)) -> (Vec<u8>, usize) {
+    let started = Instant::now();
+    let mut result = (Vec::new(), 0);
+
+    for _ in 0..ROUNDS {
+        result = encode();
+    }
+
+    let seconds = started.elapsed().as_secs_f64();
+    let megabytes = (MESSAGE_SIZE as f64 * ROUNDS as f64) / (1024.0 * 1024.0);
+
+    println!("{:>12}: {:8.1} MiB/s", name, megabytes / seconds);
+
+    result
+}
+
+fn main() {
+    let message = message();
+
+    let mut frequencies = [0u64; 256];
+    compressor::histogram::count(&message, &mut frequencies);
+
+    let huffman_tree =
+        compressor::tree::build(compressor::tree::from_frequencies(&frequencies)).unwrap();
+    let codes = compressor::calc_codes(&huffman_tree).unwrap();
+
+    println!("Encoding {} MiB, {} rounds", MESSAGE_SIZE >> 20, ROUNDS);
+
+    let bytewise = throughput("bytewise", || compress_bytewise(&message, &codes));
+    let bit_writer = throughput("bit writer", || {
+        compressor::compress(&message, &codes).unwrap()
+    });
+
+    assert_eq!(bytewise, bit_writer);
+}
@@ -0,0 +1,143 @@
+use huffman_coding::compressor::{self, histogram};
+use huffman_coding::{stream_helpers, Node};
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::time::Instant;
+
+mod common;
+
+// `HISTOGRAM_BENCH_MIB` overrides the size of the input file
+const DEFAULT_FILE_MIB: usize = 1024;
+// the legacy counter takes a seek per byte, it is only run on a prefix of the file
+const LEGACY_MIB: usize = 8;
+const CHUNK_MIB: usize = 16;
+
+// `tree::init` before it counted in bulk: a byte per `read_exact`, a position query per byte and
+// a `HashMap` of counts.
+fn init_legacy<T: Read + Seek>(reader: &mut T) -> BinaryHeap<Box<Node>> {
+    let mut byte = [0u8];
+    let stream_len = stream_helpers::stream_length(reader).unwrap();
+
+    let mut frequencies = HashMap::<u8, u64>::new();
+
+    while stream_helpers::stream_current_position(reader).unwrap() != stream_len {
+        reader.read_exact(&mut byte).unwrap();
+        *frequencies.entry(byte[0]).or_insert(0) += 1;
+    }
+
+    frequencies
+        .into_iter()
+        .map(|(byte, frequency)| Box::new(Node::new_leaf(byte, frequency)))
+        .collect()
+}
+
+fn frequencies(leaves: BinaryHeap<Box<Node>>) -> [u64; 256] {
+    let mut frequencies = [0u64; 256];
+
+    for leaf in leaves {
+        frequencies[leaf.symbol.unwrap() as usize] = leaf.frequency;
+    }
+
+    frequencies
+}
+
+fn throughput(name: &str, mib: usize, count: impl FnOnce() -> [u64; 256]) -> ([u64; 256], f64) {
+    let started = Instant::now();
+    let frequencies = count();
+    let mib_per_second = mib as f64 / started.elapsed().as_secs_f64();
+
+    println!("{:>16}: {:10.1} MiB/s", name, mib_per_second);
+
+    (frequencies, mib_per_second)
+}
+
+fn main() {
+    let file_mib = std::env::var("HISTOGRAM_BENCH_MIB")
+        .ok()
+        .and_then(|mib| mib.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_FILE_MIB)
+        .max(CHUNK_MIB);
+
+    let path = std::env::temp_dir().join(format!("huffman-histogram-{}", std::process::id()));
+    let legacy_path = path.with_extension("legacy");
+
+    let mut chunk = vec![0u8; CHUNK_MIB * 1024 * 1024];
+    let mut state = common::SEED;
+    let rounds = file_mib / CHUNK_MIB;
+    let mut legacy_expected = [0u64; 256];
+
+    {
+        let mut writer = BufWriter::new(File::create(&path).unwrap());
+
+        for round in 0..rounds {
+            common::fill(&mut chunk, &mut state);
+            writer.write_all(&chunk).unwrap();
+
+            if round == 0 {
+                let prefix = &chunk[..LEGACY_MIB * 1024 * 1024];
+
+                fs::write(&legacy_path, prefix).unwrap();
+                histogram::count(prefix, &mut legacy_expected);
+            }
+        }
+    }
+
+    println!(
+        "Counting {} MiB from a file, the legacy counter on the first {} MiB",
+        rounds * CHUNK_MIB,
+        LEGACY_MIB
+    );
+
+    let (legacy, legacy_speed) = throughput("legacy init", LEGACY_MIB, || {
+        let mut reader = BufReader::new(File::open(&legacy_path).unwrap());
+        frequencies(init_legacy(&mut reader))
+    });
+
+    let (bulk, bulk_speed) = throughput("tree::init", rounds * CHUNK_MIB, || {
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        frequencies(compressor::tree::init(&mut reader).unwrap())
+    });
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&legacy_path).unwrap();
+
+    assert_eq!(legacy, legacy_expected);
+    assert_eq!(bulk.iter().sum::<u64>(), (rounds * chunk.len()) as u64);
+
+    println!("{:>16}: {:10.0}x", "speedup", bulk_speed / legacy_speed);
+
+    // in memory, where interleaving pays off most on runs of the same byte
+    compare_tables("text", &chunk, rounds);
+
+    chunk.fill(b'a');
+    compare_tables("a single byte", &chunk, rounds);
+}
+
+fn compare_tables(input: &str, chunk: &[u8], rounds: usize) {
+    println!("Counting {} MiB of {} in memory", rounds * CHUNK_MIB, input);
+
+    let (single, _) = throughput("single table", rounds * CHUNK_MIB, || {
+        let mut frequencies = [0u64; 256];
+
+        for _ in 0..rounds {
+            for byte in chunk {
+                frequencies[*byte as usize] += 1;
+            }
+        }
+
+        frequencies
+    });
+
+    let (interleaved, _) = throughput("interleaved", rounds * CHUNK_MIB, || {
+        let mut frequencies = [0u64; 256];
+
+        for _ in 0..rounds {
+            histogram::count(chunk, &mut frequencies);
+        }
+
+        frequencies
+    });
+
+    assert_eq!(single, interleaved);
+}
@@ -0,0 +1,303 @@
+use crate::error::Error;
+use std::io::{ErrorKind, Read};
+
+const STAGING_SIZE: usize = 4096;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BitOrder {
+    // the first bit of a byte is its most significant one, values are big-endian
+    MsbFirst,
+    // the first bit of a byte is its least significant one, values are little-endian
+    LsbFirst,
+}
+
+// Reads bits from any byte source. Bytes are read ahead in blocks, so wrap the source
+// in `Read::take` when something else has to be read after the bit stream.
+pub struct BitReader<R: Read> {
+    reader: R,
+    order: BitOrder,
+    staging: Box<[u8]>,
+    staging_pos: usize,
+    staging_len: usize,
+    buffer: u64,
+    buffer_len: u8,
+    consumed: u64,
+}
+
+impl<R: Read> BitReader<R> {
+    pub const MAX_PEEK: u8 = 57;
+
+    pub fn new(reader: R) -> BitReader<R> {
+        BitReader::with_order(reader, BitOrder::MsbFirst)
+    }
+
+    pub fn with_order(reader: R, order: BitOrder) -> BitReader<R> {
+        BitReader {
+            reader,
+            order,
+            staging: vec![0u8; STAGING_SIZE].into_boxed_slice(),
+            staging_pos: 0,
+            staging_len: 0,
+            buffer: 0,
+            buffer_len: 0,
+            consumed: 0,
+        }
+    }
+
+    // Returns the next `count` bits without consuming them; missing bits past the end are zeros.
+    #[inline]
+    pub fn peek_bits(&mut self, count: u8) -> std::io::Result<u64> {
+        if count > BitReader::<R>::MAX_PEEK {
+            return Err(Error::InvalidArgument("Cannot peek more than 57 bits at once").into());
+        }
+
+        if count == 0 {
+            return Ok(0);
+        }
+
+        if self.buffer_len < count {
+            self.refill()?;
+        }
+
+        Ok(match self.order {
+            BitOrder::MsbFirst => self.buffer >> (64 - count),
+            BitOrder::LsbFirst => self.buffer & ((1u64 << count) - 1),
+        })
+    }
+
+    #[inline]
+    pub fn consume(&mut self, count: u8) -> std::io::Result<()> {
+        if count > BitReader::<R>::MAX_PEEK {
+            return Err(Error::InvalidArgument("Cannot consume more than 57 bits at once").into());
+        }
+
+        if self.buffer_len < count {
+            self.refill()?;
+
+            if self.buffer_len < count {
+                return Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Bit stream ended unexpectedly",
+                ));
+            }
+        }
+
+        match self.order {
+            BitOrder::MsbFirst => self.buffer <<= count,
+            BitOrder::LsbFirst => self.buffer >>= count,
+        }
+
+        self.buffer_len -= count;
+        self.consumed += count as u64;
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn read_bits(&mut self, count: u8) -> std::io::Result<u64> {
+        let value = self.peek_bits(count)?;
+        self.consume(count)?;
+
+        Ok(value)
+    }
+
+    #[inline]
+    pub fn read_bit(&mut self) -> std::io::Result<bool> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    // Skips the bits left in the current byte.
+    pub fn align(&mut self) -> std::io::Result<()> {
+        let padding = (8 - self.consumed % 8) % 8;
+
+        self.consume(padding as u8)
+    }
+
+    pub fn is_aligned(&self) -> bool {
+        self.consumed.is_multiple_of(8)
+    }
+
+    pub fn bits_consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    // Tops the buffer up to at least 57 bits unless the source is exhausted.
+    #[inline(never)]
+    fn refill(&mut self) -> std::io::Result<()> {
+        if self.staging_len - self.staging_pos >= 8 {
+            self.refill_word();
+            return Ok(());
+        }
+
+        while self.buffer_len <= 56 {
+            if self.staging_pos == self.staging_len {
+                self.staging_len = read_some(&mut self.reader, &mut self.staging)?;
+                self.staging_pos = 0;
+
+                if self.staging_len == 0 {
+                    break;
+                }
+            }
+
+            let byte = self.staging[self.staging_pos] as u64;
+            self.staging_pos += 1;
+
+            match self.order {
+                BitOrder::MsbFirst => self.buffer |= byte << (56 - self.buffer_len),
+                BitOrder::LsbFirst => self.buffer |= byte << self.buffer_len,
+            }
+
+            self.buffer_len += 8;
+        }
+
+        Ok(())
+    }
+
+    fn refill_word(&mut self) {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&self.staging[self.staging_pos..self.staging_pos + 8]);
+
+        let taken_bytes = (64 - self.buffer_len) / 8;
+        let taken_len = taken_bytes * 8;
+
+        match self.order {
+            BitOrder::MsbFirst => {
+                let mut bits = u64::from_be_bytes(word);
+
+                if taken_len < 64 {
+                    bits &= !(u64::MAX >> taken_len);
+                }
+
+                self.buffer |= bits >> self.buffer_len;
+            }
+            BitOrder::LsbFirst => {
+                let mut bits = u64::from_le_bytes(word);
+
+                if taken_len < 64 {
+                    bits &= (1u64 << taken_len) - 1;
+                }
+
+                self.buffer |= bits << self.buffer_len;
+            }
+        }
+
+        self.staging_pos += taken_bytes as usize;
+        self.buffer_len += taken_len;
+    }
+}
+
+impl BitReader<&[u8]> {
+    pub fn remaining_bits(&self) -> u64 {
+        (self.reader.len() + self.staging_len - self.staging_pos) as u64 * 8
+            + self.buffer_len as u64
+    }
+}
+
+fn read_some<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    loop {
+        match reader.read(buf) {
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bit_reader::{BitOrder, BitReader};
+    use crate::error::Error;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_read_msb_first() {
+        let stream = [0b10110100u8, 0b00000001, 0xff];
+        let mut reader = BitReader::new(&stream[..]);
+
+        assert_eq!(reader.remaining_bits(), 24);
+        assert!(reader.read_bit().unwrap());
+        assert_eq!(reader.read_bits(3).unwrap(), 0b011);
+        assert_eq!(reader.peek_bits(12).unwrap(), 0b0100_0000_0001);
+        assert_eq!(reader.bits_consumed(), 4);
+        assert_eq!(reader.remaining_bits(), 20);
+
+        reader.consume(8).unwrap();
+        assert!(!reader.is_aligned());
+
+        reader.align().unwrap();
+        assert!(reader.is_aligned());
+        assert_eq!(reader.remaining_bits(), 8);
+
+        assert_eq!(reader.peek_bits(12).unwrap(), 0b1111_1111_0000);
+        assert_eq!(reader.read_bits(8).unwrap(), 0xff);
+        assert_eq!(reader.remaining_bits(), 0);
+    }
+
+    #[test]
+    fn test_read_lsb_first() {
+        let stream = [0b10110100u8, 0b00000001];
+        let mut reader = BitReader::with_order(&stream[..], BitOrder::LsbFirst);
+
+        assert!(!reader.read_bit().unwrap());
+        assert_eq!(reader.read_bits(3).unwrap(), 0b010);
+        assert_eq!(reader.read_bits(6).unwrap(), 0b011011);
+        assert_eq!(reader.read_bits(6).unwrap(), 0b000000);
+        assert_eq!(reader.remaining_bits(), 0);
+    }
+
+    #[test]
+    fn test_read_words() {
+        let stream: Vec<u8> = (0..64u8).collect();
+        let mut reader = BitReader::new(stream.as_slice());
+
+        for chunk in stream.chunks(7) {
+            let mut word = [0u8; 8];
+            word[8 - chunk.len()..].copy_from_slice(chunk);
+
+            let count = chunk.len() as u8 * 8;
+            assert_eq!(reader.read_bits(count).unwrap(), u64::from_be_bytes(word));
+        }
+    }
+
+    #[test]
+    fn test_read_from_io_reader() {
+        let stream: Vec<u8> = (0..10_000u32).map(|index| index as u8).collect();
+        let mut reader = BitReader::new(std::io::Cursor::new(&stream));
+
+        for expected in &stream {
+            assert_eq!(reader.read_bits(8).unwrap(), *expected as u64);
+        }
+
+        assert_eq!(reader.bits_consumed(), 80_000);
+    }
+
+    #[test]
+    fn test_read_past_the_end() {
+        let stream = [0b11000000u8];
+        let mut reader = BitReader::new(&stream[..]);
+
+        assert_eq!(reader.peek_bits(10).unwrap(), 0b11_0000_0000);
+
+        let err = reader.consume(10).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_read_too_many_bits() {
+        let mut reader = BitReader::new(&[0u8; 16][..]);
+
+        for err in [
+            reader.peek_bits(58).err().unwrap(),
+            reader.consume(64).err().unwrap(),
+            reader.read_bits(60).err().unwrap(),
+        ] {
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            assert!(matches!(Error::from(err), Error::InvalidArgument(_)));
+        }
+
+        assert_eq!(reader.bits_consumed(), 0);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::error::Error;
+use crate::huffman;
+use std::io::Write;
+
+// Accumulates bits MSB first in a 64-bit register and writes it out word by word.
+pub struct BitWriter<W: Write> {
+    writer: W,
+    register: u64,
+    register_len: u8,
+    total_len: u64,
+}
+
+impl<W: Write> BitWriter<W> {
+    pub fn new(writer: W) -> BitWriter<W> {
+        BitWriter {
+            writer,
+            register: 0,
+            register_len: 0,
+            total_len: 0,
+        }
+    }
+
+    pub fn write_bits(&mut self, value: u64, count: u8) -> std::io::Result<()> {
+        if count > 64 {
+            return Err(Error::InvalidArgument("Cannot write more than 64 bits at once").into());
+        }
+
+        if count == 0 {
+            return Ok(());
+        }
+
+        let value = if count == 64 {
+            value
+        } else {
+            value & ((1u64 << count) - 1)
+        };
+
+        let free_len = 64 - self.register_len;
+
+        if count < free_len {
+            self.register |= value << (free_len - count);
+            self.register_len += count;
+        } else {
+            let spilled_len = count - free_len;
+
+            self.register |= value >> spilled_len;
+            self.writer.write_all(&self.register.to_be_bytes())?;
+
+            self.register = if spilled_len == 0 {
+                0
+            } else {
+                value << (64 - spilled_len)
+            };
+            self.register_len = spilled_len;
+        }
+
+        self.total_len += count as u64;
+
+        Ok(())
+    }
+
+    pub fn write_code(&mut self, code: &huffman::HuffmanCode) -> std::io::Result<()> {
+        self.write_bits(code.bin_repres(), code.bin_length())
+    }
+
+    // Pads the current byte with zeros.
+    pub fn align(&mut self) -> std::io::Result<()> {
+        let padding = (8 - self.total_len % 8) % 8;
+
+        self.write_bits(0, padding as u8)
+    }
+
+    pub fn bit_len(&self) -> u64 {
+        self.total_len
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    // Writes out the whole bytes pending in the register and flushes the writer, `align` first
+    // to get every bit out.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        let whole_bytes = self.register_len / 8;
+
+        self.writer
+            .write_all(&self.register.to_be_bytes()[..whole_bytes as usize])?;
+
+        self.register <<= whole_bytes * 8;
+        self.register_len -= whole_bytes * 8;
+
+        self.writer.flush()
+    }
+
+    // Writes out the pending bits, zero padding the last byte.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        let pending_bytes = (self.register_len as usize).div_ceil(8);
+
+        self.writer
+            .write_all(&self.register.to_be_bytes()[..pending_bytes])?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bit_writer::BitWriter;
+    use crate::error::Error;
+    use crate::huffman::HuffmanCode;
+
+    #[test]
+    fn test_write_bits() {
+        let mut writer = BitWriter::new(Vec::new());
+
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bits(0b0, 1).unwrap();
+        writer.write_bits(0b1_1110_0001, 9).unwrap();
+        writer
+            .write_code(&HuffmanCode::from_parts(0b11, 2).unwrap())
+            .unwrap();
+
+        assert_eq!(writer.bit_len(), 15);
+        assert_eq!(writer.finish().unwrap(), vec![0b10101111, 0b00001110]);
+    }
+
+    #[test]
+    fn test_write_words() {
+        let mut writer = BitWriter::new(Vec::new());
+
+        writer.write_bits(0b1, 1).unwrap();
+        writer.write_bits(u64::MAX, 64).unwrap();
+        writer.write_bits(0x0123_4567_89ab_cdef, 63).unwrap();
+        writer.write_bits(0xff, 4).unwrap();
+
+        assert_eq!(writer.bit_len(), 132);
+
+        let written = writer.finish().unwrap();
+        let expected_tail = (1u64 << 63) | 0x0123_4567_89ab_cdef;
+
+        assert_eq!(written.len(), 17);
+        assert_eq!(&written[..8], &[0xff; 8]);
+        assert_eq!(&written[8..16], &expected_tail.to_be_bytes());
+        assert_eq!(written[16], 0b11110000);
+    }
+
+    #[test]
+    fn test_align() {
+        let mut writer = BitWriter::new(Vec::new());
+
+        writer.align().unwrap();
+        writer.write_bits(0b11, 2).unwrap();
+        writer.align().unwrap();
+        writer.write_bits(0b1, 1).unwrap();
+
+        assert_eq!(writer.bit_len(), 9);
+        assert_eq!(writer.finish().unwrap(), vec![0b11000000, 0b10000000]);
+    }
+
+    #[test]
+    fn test_write_too_many_bits() {
+        let mut writer = BitWriter::new(Vec::new());
+
+        let err = writer.write_bits(0, 65).err().unwrap();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(matches!(Error::from(err), Error::InvalidArgument(_)));
+        assert_eq!(writer.bit_len(), 0);
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut writer = BitWriter::new(Vec::new());
+
+        writer.write_bits(0xabc, 12).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref(), &vec![0xab]);
+
+        writer.align().unwrap();
+        writer.flush().unwrap();
+        writer.write_bits(0b1, 1).unwrap();
+
+        assert_eq!(writer.bit_len(), 17);
+        assert_eq!(writer.finish().unwrap(), vec![0xab, 0xc0, 0b10000000]);
+    }
+}
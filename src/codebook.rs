@@ -0,0 +1,222 @@
+use crate::compressor;
+use crate::decompressor::table;
+use crate::error::{Error, Result};
+use crate::huffman::{self, Symbol};
+use std::collections::HashMap;
+
+// Canonical codes for a fixed set of symbols. A code book built once, for example from the
+// frequencies of a training corpus, codes any number of messages; its code lengths are all that
+// has to be stored to rebuild it.
+#[derive(Clone, Debug)]
+pub struct CodeBook<S = u8> {
+    code_lengths: HashMap<S, u8>,
+    codes: HashMap<S, huffman::HuffmanCode>,
+}
+
+// The codes follow from the lengths.
+impl<S: Symbol> PartialEq for CodeBook<S> {
+    fn eq(&self, other: &CodeBook<S>) -> bool {
+        self.code_lengths == other.code_lengths
+    }
+}
+
+impl CodeBook<u8> {
+    // Bytes with a frequency of zero get no code.
+    pub fn from_frequencies(frequencies: &[u64; 256]) -> Result<CodeBook> {
+        CodeBook::from_lengths(compressor::lengths_from_frequencies(frequencies)?)
+    }
+
+    // `code_lengths[byte]` is the length of the code of `byte`, zero for none.
+    pub fn from_code_lengths(code_lengths: &[u8]) -> Result<CodeBook> {
+        if code_lengths.len() > 256 {
+            return Err(Error::InvalidArgument(
+                "There are only 256 bytes to assign codes",
+            ));
+        }
+
+        CodeBook::from_lengths(
+            code_lengths
+                .iter()
+                .enumerate()
+                .filter(|(_, length)| **length > 0)
+                .map(|(byte, length)| (byte as u8, *length))
+                .collect(),
+        )
+    }
+}
+
+impl<S: Symbol> CodeBook<S> {
+    // Counts of the same symbol add up, symbols counted zero times get no code.
+    pub fn from_counts<I: IntoIterator<Item = (S, u64)>>(counts: I) -> Result<CodeBook<S>> {
+        let mut frequencies = HashMap::<S, u64>::new();
+
+        for (symbol, count) in counts {
+            let frequency = frequencies.entry(symbol).or_insert(0);
+
+            *frequency = frequency.checked_add(count).ok_or(Error::InvalidArgument(
+                "The counts of a symbol overflow a u64",
+            ))?;
+        }
+
+        CodeBook::from_lengths(compressor::lengths_from_symbol_frequencies(&frequencies)?)
+    }
+
+    pub fn from_lengths(mut code_lengths: HashMap<S, u8>) -> Result<CodeBook<S>> {
+        code_lengths.retain(|_, length| *length > 0);
+
+        Ok(CodeBook {
+            codes: compressor::codes_from_lengths(&code_lengths)?,
+            code_lengths,
+        })
+    }
+
+    pub fn code_lengths(&self) -> &HashMap<S, u8> {
+        &self.code_lengths
+    }
+
+    pub fn codes(&self) -> &HashMap<S, huffman::HuffmanCode> {
+        &self.codes
+    }
+
+    pub fn code(&self, symbol: &S) -> Option<&huffman::HuffmanCode> {
+        self.codes.get(symbol)
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    pub fn decode_tree(&self) -> Result<Option<Box<huffman::Node<S>>>> {
+        compressor::tree::from_codes(&self.codes)
+    }
+
+    pub fn decode_table(&self) -> table::DecodeTable<S> {
+        table::DecodeTable::new(&self.codes)
+    }
+
+    // Returns the coded bits and their number, as `compressor::compress` does.
+    pub fn encode(&self, symbols: &[S]) -> Result<(Vec<u8>, usize)> {
+        compressor::compress_symbols(symbols, &self.codes)
+    }
+
+    // Builds a decode table on every call, keep one from `decode_table` to decode many messages.
+    pub fn decode(&self, compressed_stream: &(Vec<u8>, usize)) -> Result<Vec<S>> {
+        table::decompress(compressed_stream, &self.decode_table())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codebook::CodeBook;
+    use crate::error::Error;
+    use crate::{compressor, decompressor};
+
+    #[test]
+    fn test_from_frequencies() {
+        let corpus = b"abracadabra, or abacadabra, dabracadabra";
+        let mut frequencies = [0u64; 256];
+
+        for byte in corpus {
+            frequencies[*byte as usize] += 1;
+        }
+
+        let code_book = CodeBook::from_frequencies(&frequencies).unwrap();
+
+        assert_eq!(code_book.len(), 8);
+        assert_eq!(code_book.code(&b'a').unwrap().bin_length(), 1);
+        assert!(code_book.code(&b'z').is_none());
+
+        // trained once, reused for other messages over the same bytes
+        let decode_table = code_book.decode_table();
+
+        for message in [&b"cab"[..], b"", b"abba, dad"] {
+            let compressed = code_book.encode(message).unwrap();
+
+            assert_eq!(
+                decompressor::table::decompress(&compressed, &decode_table).unwrap(),
+                message
+            );
+        }
+
+        assert!(matches!(
+            code_book.encode(b"zebra"),
+            Err(Error::SymbolNotInTable(symbol)) if symbol == "122"
+        ));
+    }
+
+    #[test]
+    fn test_from_counts() {
+        let code_book =
+            CodeBook::from_counts(vec![(1000u32, 5), (7, 1), (1000, 5), (42, 0), (3, 2)]).unwrap();
+
+        assert_eq!(code_book.len(), 3);
+        assert_eq!(code_book.code_lengths()[&1000], 1);
+        assert!(code_book.code(&42).is_none());
+
+        let message = vec![1000, 3, 7, 1000, 1000];
+        let compressed = code_book.encode(&message).unwrap();
+
+        assert_eq!(compressed.1, 1 + 2 + 2 + 1 + 1);
+        assert_eq!(code_book.decode(&compressed).unwrap(), message);
+
+        let decode_tree = code_book.decode_tree().unwrap().unwrap();
+        assert_eq!(
+            decompressor::decompress(&compressed, &decode_tree).unwrap(),
+            message
+        );
+
+        assert!(CodeBook::<u32>::from_counts(Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_huge_counts() {
+        let code_book = CodeBook::from_frequencies(&[u64::MAX; 256]).unwrap();
+        assert!(code_book.code_lengths().values().all(|length| *length == 8));
+
+        let mut frequencies = [0u64; 256];
+        frequencies[..3].copy_from_slice(&[u64::MAX, u64::MAX / 2, 1]);
+
+        let code_book = CodeBook::from_frequencies(&frequencies).unwrap();
+        assert_eq!(code_book.code_lengths()[&0], 1);
+        assert_eq!(code_book.code_lengths()[&2], 2);
+
+        let code_book =
+            CodeBook::from_counts((0..1000u32).map(|symbol| (symbol, u64::MAX))).unwrap();
+        assert_eq!(code_book.len(), 1000);
+
+        let lengths = compressor::lengths_from_frequencies(&[u64::MAX / 2; 256]).unwrap();
+        assert_eq!(lengths.len(), 256);
+
+        assert!(matches!(
+            CodeBook::from_counts(vec![(1u32, u64::MAX), (2, 1), (1, 1)]),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_code_lengths() {
+        let mut code_lengths = [0u8; 256];
+        code_lengths[b'x' as usize] = 1;
+        code_lengths[b'y' as usize] = 2;
+        code_lengths[b'z' as usize] = 2;
+
+        let code_book = CodeBook::from_code_lengths(&code_lengths).unwrap();
+        let rebuilt = CodeBook::from_code_lengths(&code_lengths[..=b'z' as usize]).unwrap();
+
+        assert_eq!(code_book.code_lengths(), rebuilt.code_lengths());
+        assert_eq!(code_book.code(&b'x').unwrap().bin_repres(), 0b0);
+        assert_eq!(code_book.code(&b'y').unwrap().bin_repres(), 0b10);
+        assert_eq!(code_book.code(&b'z').unwrap().bin_repres(), 0b11);
+
+        code_lengths[b'w' as usize] = 1;
+        assert!(matches!(
+            CodeBook::from_code_lengths(&code_lengths),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(CodeBook::from_code_lengths(&[1u8; 257]).is_err());
+    }
+}
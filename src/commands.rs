@@ -0,0 +1,671 @@
+use crate::run_config::{Coding, Command, Config};
+use huffman_coding::container::{self, BlockKind};
+use huffman_coding::decompressor::live::LiveStream;
+use huffman_coding::{shared_table, Error, HuffmanDecoder, Result, SharedTable};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub const SUFFIX: &str = ".huff";
+
+// every bench stage is repeated at least this long
+const BENCH_DURATION: Duration = Duration::from_secs(1);
+
+// `table` is the one loaded from `config.table`.
+pub fn run(config: &Config, table: Option<&SharedTable>, file: &str) -> Result<()> {
+    match config.command {
+        Command::Compress | Command::Decompress => convert(config, table, file),
+        Command::Test => test(config, table, file),
+        Command::Info => info(config, file),
+        Command::Bench => bench(config, table, file),
+        // runs once over all files, see `train`
+        Command::Train | Command::Help => Ok(()),
+    }
+}
+
+pub fn load_table(path: &str) -> Result<SharedTable> {
+    SharedTable::read(&mut BufReader::new(File::open(Path::new(path))?))
+}
+
+pub fn display_name(file: &str) -> &str {
+    if file == "-" {
+        "stdin"
+    } else {
+        file
+    }
+}
+
+// Counts the bytes passing through a reader or a writer.
+struct Counter<T> {
+    inner: T,
+    count: u64,
+}
+
+impl<T> Counter<T> {
+    fn new(inner: T) -> Counter<T> {
+        Counter { inner, count: 0 }
+    }
+}
+
+impl<T: Read> Read for Counter<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_len = self.inner.read(buf)?;
+        self.count += read_len as u64;
+
+        Ok(read_len)
+    }
+}
+
+impl<T: Write> Write for Counter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written_len = self.inner.write(buf)?;
+        self.count += written_len as u64;
+
+        Ok(written_len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn open_input(file: &str) -> Result<Box<dyn Read>> {
+    if file == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+
+    Ok(Box::new(BufReader::new(File::open(Path::new(file))?)))
+}
+
+// `None` stands for standard output.
+fn output_path(config: &Config, file: &str) -> Result<Option<String>> {
+    if let Some(output) = &config.output {
+        return Ok(Some(output.clone()).filter(|output| output != "-"));
+    }
+
+    if config.to_stdout || file == "-" {
+        return Ok(None);
+    }
+
+    if config.command == Command::Compress {
+        if file.ends_with(SUFFIX) {
+            return Err(Error::InvalidArgument("Input already has the .huff suffix"));
+        }
+
+        return Ok(Some(format!("{}{}", file, SUFFIX)));
+    }
+
+    match file.strip_suffix(SUFFIX) {
+        Some(name) if !name.is_empty() => Ok(Some(String::from(name))),
+        _ => Err(Error::InvalidArgument(
+            "Input does not have the .huff suffix, use -c or -o",
+        )),
+    }
+}
+
+fn transcode<R: Read, W: Write>(
+    config: &Config,
+    table: Option<&SharedTable>,
+    compressing: bool,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<()> {
+    if let Some(table) = table {
+        if compressing {
+            return huffman_coding::compress_with_table(reader, writer, table);
+        }
+
+        let mut decoder = HuffmanDecoder::with_shared_table(reader, table);
+        decoder.set_verify(config.verify);
+        io::copy(&mut decoder, writer)?;
+
+        return Ok(());
+    }
+
+    if !compressing {
+        return if config.verify {
+            huffman_coding::decompress(reader, writer)
+        } else {
+            huffman_coding::decompress_unverified(reader, writer)
+        };
+    }
+
+    match config.coding {
+        Coding::Blocks(block_size) => {
+            huffman_coding::compress_with_block_size(reader, writer, block_size)
+        }
+        Coding::TwoPass => huffman_coding::compress_two_pass(reader, writer),
+        Coding::Adaptive => huffman_coding::compress_adaptive(reader, writer),
+        Coding::Context => huffman_coding::compress_context(reader, writer),
+        Coding::Live => huffman_coding::compress_live(reader, writer),
+    }
+}
+
+fn percentage(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        return 100.0;
+    }
+
+    part as f64 * 100.0 / whole as f64
+}
+
+// Writing to the input would truncate it before it is read, even with -f.
+fn is_same_file(file: &str, output: &str) -> Result<bool> {
+    if file == "-" || !Path::new(output).exists() {
+        return Ok(false);
+    }
+
+    Ok(fs::canonicalize(Path::new(file))? == fs::canonicalize(Path::new(output))?)
+}
+
+// The input is replaced only by an output named after it.
+fn replaces_input(config: &Config, output: Option<&str>) -> bool {
+    config.output.is_none() && output.is_some() && !config.keep
+}
+
+fn convert(config: &Config, table: Option<&SharedTable>, file: &str) -> Result<()> {
+    let compressing = config.command == Command::Compress;
+    let output = output_path(config, file)?;
+
+    if let Some(path) = output.as_deref() {
+        if is_same_file(file, path)? {
+            return Err(Error::InvalidArgument("Input and output are the same file"));
+        }
+    }
+
+    if !config.force {
+        if compressing && output.is_none() && io::stdout().is_terminal() {
+            return Err(Error::InvalidArgument(
+                "Refusing to write compressed data to a terminal, use -f to force",
+            ));
+        }
+
+        if !compressing && file == "-" && io::stdin().is_terminal() {
+            return Err(Error::InvalidArgument(
+                "Refusing to read compressed data from a terminal, use -f to force",
+            ));
+        }
+
+        if output
+            .as_deref()
+            .is_some_and(|path| Path::new(path).exists())
+        {
+            return Err(Error::InvalidArgument(
+                "Output file already exists, use -f to overwrite",
+            ));
+        }
+    }
+
+    let mut reader = Counter::new(open_input(file)?);
+
+    let written = match &output {
+        None => {
+            let mut writer = Counter::new(io::stdout().lock());
+            transcode(config, table, compressing, &mut reader, &mut writer)?;
+            writer.flush()?;
+
+            writer.count
+        }
+        Some(path) => {
+            let mut writer = Counter::new(BufWriter::new(File::create(Path::new(path))?));
+            let result =
+                transcode(config, table, compressing, &mut reader, &mut writer).and_then(|_| {
+                    writer.flush()?;
+                    Ok(())
+                });
+
+            // no half-written files are left behind
+            if let Err(err) = result {
+                drop(writer);
+                fs::remove_file(Path::new(path))?;
+                return Err(err);
+            }
+
+            writer.count
+        }
+    };
+
+    let replaced = replaces_input(config, output.as_deref());
+
+    if replaced {
+        fs::remove_file(Path::new(file))?;
+    }
+
+    if config.verbosity > 0 {
+        let (original, compressed) = if compressing {
+            (reader.count, written)
+        } else {
+            (written, reader.count)
+        };
+
+        eprint!(
+            "{}: {} -> {} bytes, {:.1}% of the original",
+            display_name(file),
+            reader.count,
+            written,
+            percentage(compressed, original)
+        );
+
+        match output.as_deref() {
+            Some(path) if replaced => eprintln!(", replaced with {}", path),
+            Some(path) => eprintln!(", written to {}", path),
+            None => eprintln!(),
+        }
+    }
+
+    Ok(())
+}
+
+fn test(config: &Config, table: Option<&SharedTable>, file: &str) -> Result<()> {
+    let mut reader = open_input(file)?;
+    let mut writer = Counter::new(io::sink());
+
+    match table {
+        Some(table) => huffman_coding::decompress_with_table(&mut reader, &mut writer, table)?,
+        None => huffman_coding::decompress(&mut reader, &mut writer)?,
+    }
+
+    if config.verbosity > 0 {
+        eprintln!("{}: OK, {} bytes", display_name(file), writer.count);
+    }
+
+    Ok(())
+}
+
+fn kind_name(kind: &BlockKind) -> &'static str {
+    match kind {
+        BlockKind::Huffman { .. } => "huffman",
+        BlockKind::Adaptive => "adaptive",
+        BlockKind::Stored => "stored",
+        BlockKind::Context { .. } => "context",
+        BlockKind::Shared { .. } => "shared",
+        BlockKind::Live => "live",
+    }
+}
+
+fn info(config: &Config, file: &str) -> Result<()> {
+    let mut reader = Counter::new(open_input(file)?);
+    container::read_file_header(&mut reader)?;
+
+    let (mut huffman_blocks, mut adaptive_blocks, mut stored_blocks, mut context_blocks) =
+        (0usize, 0usize, 0usize, 0usize);
+    let (mut shared_blocks, mut live_streams) = (0usize, 0usize);
+    let mut original_len = 0u64;
+    let mut block_index = 0usize;
+
+    loop {
+        let block_start = reader.count;
+
+        let header = match container::read_block_header(&mut reader)? {
+            Some(header) => header,
+            None => break,
+        };
+
+        container::read_payload(&mut reader, &header)?;
+
+        // live streams do not know their length upfront, they are decoded to get it
+        let (mut block_len, mut block_checksum) = (header.original_len, header.checksum);
+
+        if let BlockKind::Live = header.kind {
+            let mut stream = LiveStream::new();
+            let mut chunk = Vec::new();
+
+            while !stream.decode_chunk(&mut reader, &mut chunk, config.verify)? {
+                block_len += chunk.len() as u64;
+                chunk.clear();
+            }
+
+            block_len += chunk.len() as u64;
+            block_checksum = stream.checksum();
+        }
+
+        match header.kind {
+            BlockKind::Huffman { .. } => huffman_blocks += 1,
+            BlockKind::Adaptive => adaptive_blocks += 1,
+            BlockKind::Stored => stored_blocks += 1,
+            BlockKind::Context { .. } => context_blocks += 1,
+            BlockKind::Shared { .. } => shared_blocks += 1,
+            BlockKind::Live => live_streams += 1,
+        }
+
+        original_len += block_len;
+
+        if config.verbosity > 0 {
+            let symbols = match &header.kind {
+                BlockKind::Huffman { code_lengths } => format!(", {} symbols", code_lengths.len()),
+                BlockKind::Context { tables } => {
+                    format!(", {} own context tables", tables.own_table_count())
+                }
+                BlockKind::Shared { table_id } => format!(", shared table {:08x}", table_id),
+                _ => String::new(),
+            };
+
+            println!(
+                "  block {}: {}, {} -> {} bytes{}, crc32 {:08x}",
+                block_index,
+                kind_name(&header.kind),
+                block_len,
+                reader.count - block_start,
+                symbols,
+                block_checksum
+            );
+        }
+
+        block_index += 1;
+    }
+
+    println!(
+        "{}: {} blocks ({} huffman, {} adaptive, {} stored, {} context, {} shared, {} live), {} -> {} bytes, {:.1}% of the original",
+        display_name(file),
+        block_index,
+        huffman_blocks,
+        adaptive_blocks,
+        stored_blocks,
+        context_blocks,
+        shared_blocks,
+        live_streams,
+        original_len,
+        reader.count,
+        percentage(reader.count, original_len)
+    );
+
+    Ok(())
+}
+
+// Runs `stage` repeatedly for at least `BENCH_DURATION`, returning the runs and their duration.
+fn repeat<F: FnMut() -> Result<()>>(mut stage: F) -> Result<(u32, Duration)> {
+    let start = Instant::now();
+    let mut runs = 0u32;
+
+    while runs == 0 || start.elapsed() < BENCH_DURATION {
+        stage()?;
+        runs += 1;
+    }
+
+    Ok((runs, start.elapsed()))
+}
+
+fn throughput(len: usize, runs: u32, duration: Duration) -> f64 {
+    len as f64 * runs as f64 / duration.as_secs_f64().max(f64::EPSILON) / 1_000_000.0
+}
+
+fn bench(config: &Config, table: Option<&SharedTable>, file: &str) -> Result<()> {
+    let mut message = Vec::new();
+    open_input(file)?.read_to_end(&mut message)?;
+
+    let mut compressed = Vec::new();
+    let (compress_runs, compress_duration) = repeat(|| {
+        compressed.clear();
+        transcode(
+            config,
+            table,
+            true,
+            &mut message.as_slice(),
+            &mut compressed,
+        )
+    })?;
+
+    let mut decompressed = Vec::new();
+    let (decompress_runs, decompress_duration) = repeat(|| {
+        decompressed.clear();
+        transcode(
+            config,
+            table,
+            false,
+            &mut compressed.as_slice(),
+            &mut decompressed,
+        )
+    })?;
+
+    if decompressed != message {
+        return Err(Error::Io(io::Error::other(
+            "Decompressed data differs from the input",
+        )));
+    }
+
+    println!(
+        "{}: {} -> {} bytes, {:.1}% of the original, compression {:.1} MB/s, decompression {:.1} MB/s",
+        display_name(file),
+        message.len(),
+        compressed.len(),
+        percentage(compressed.len() as u64, message.len() as u64),
+        throughput(message.len(), compress_runs, compress_duration),
+        throughput(message.len(), decompress_runs, decompress_duration)
+    );
+
+    // the gain of context tables over a single table per block
+    if config.coding == Coding::Context {
+        let mut order0 = Vec::new();
+        huffman_coding::compress(&mut message.as_slice(), &mut order0)?;
+
+        println!(
+            "{}: a table per block takes {} bytes, context tables save {:.1}%",
+            display_name(file),
+            order0.len(),
+            100.0 - percentage(compressed.len() as u64, order0.len() as u64)
+        );
+    }
+
+    Ok(())
+}
+
+// Trains a single table on all input files together.
+// Writes a new file that is removed again when writing fails, so no half-written file is left.
+fn write_file<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    let result = write(&mut writer).and_then(|_| {
+        writer.flush()?;
+        Ok(())
+    });
+
+    if let Err(err) = result {
+        drop(writer);
+        fs::remove_file(path)?;
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+pub fn train(config: &Config) -> Result<()> {
+    let output = config.output.as_deref().filter(|output| *output != "-");
+
+    if !config.force {
+        if output.is_none() && io::stdout().is_terminal() {
+            return Err(Error::InvalidArgument(
+                "Refusing to write a code table to a terminal, use -f to force",
+            ));
+        }
+
+        if output.is_some_and(|path| Path::new(path).exists()) {
+            return Err(Error::InvalidArgument(
+                "Output file already exists, use -f to overwrite",
+            ));
+        }
+    }
+
+    let mut frequencies = [0u64; 256];
+
+    for file in &config.files {
+        shared_table::count_frequencies(&mut open_input(file)?, &mut frequencies)?;
+    }
+
+    let table = SharedTable::from_frequencies(&frequencies)?;
+
+    match output {
+        None => {
+            let mut writer = io::stdout().lock();
+            table.write(&mut writer)?;
+            writer.flush()?;
+        }
+        Some(path) => write_file(Path::new(path), |writer| table.write(writer))?,
+    }
+
+    if config.verbosity > 0 {
+        eprintln!(
+            "table {:08x}: trained on {} bytes from {} files, written to {}",
+            table.id,
+            frequencies.iter().sum::<u64>(),
+            config.files.len(),
+            output.unwrap_or("stdout")
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands;
+    use crate::run_config::Config;
+    use huffman_coding::Error;
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn parse(args: &str) -> Config {
+        Config::new(args.split_whitespace().map(String::from)).unwrap()
+    }
+
+    // A fresh directory per test, as tests run in parallel.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("huffman-commands-{}-{}", std::process::id(), name));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn test_output_path() {
+        let output = |args: &str, file: &str| commands::output_path(&parse(args), file);
+
+        assert_eq!(
+            output("compress", "a.txt").unwrap().as_deref(),
+            Some("a.txt.huff")
+        );
+        assert_eq!(
+            output("decompress", "a.txt.huff").unwrap().as_deref(),
+            Some("a.txt")
+        );
+        assert_eq!(output("compress -c", "a.txt").unwrap(), None);
+        assert_eq!(output("compress", "-").unwrap(), None);
+        assert_eq!(
+            output("decompress -o b.txt", "a.huff").unwrap().as_deref(),
+            Some("b.txt")
+        );
+        assert_eq!(output("decompress -o -", "a.huff").unwrap(), None);
+
+        // -o takes whatever name it is given
+        assert_eq!(
+            output("decompress -o b", "a.txt").unwrap().as_deref(),
+            Some("b")
+        );
+
+        for (args, file) in [
+            ("compress", "a.txt.huff"),
+            ("decompress", "a.txt"),
+            ("decompress", ".huff"),
+        ] {
+            assert!(matches!(output(args, file), Err(Error::InvalidArgument(_))));
+        }
+    }
+
+    #[test]
+    fn test_replaces_input() {
+        let replaces =
+            |args: &str, output: Option<&str>| commands::replaces_input(&parse(args), output);
+
+        assert!(replaces("compress", Some("a.txt.huff")));
+        assert!(!replaces("compress -k", Some("a.txt.huff")));
+        assert!(!replaces("compress -c", None));
+        assert!(!replaces("compress -o b.huff", Some("b.huff")));
+    }
+
+    #[test]
+    fn test_convert_keep() {
+        let dir = scratch_dir("keep");
+        let input = dir.join("a.txt");
+        let input_name = input.to_str().unwrap();
+        fs::write(&input, b"abracadabra").unwrap();
+
+        commands::convert(&parse("compress -k"), None, input_name).unwrap();
+        assert!(input.exists());
+
+        // the output of the first run is in the way
+        assert!(matches!(
+            commands::convert(&parse("compress"), None, input_name),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        commands::convert(&parse("compress -f"), None, input_name).unwrap();
+        assert!(!input.exists());
+
+        commands::convert(&parse("decompress"), None, &format!("{}.huff", input_name)).unwrap();
+        assert_eq!(fs::read(&input).unwrap(), b"abracadabra");
+        assert!(!dir.join("a.txt.huff").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_convert_refuses_same_file() {
+        let dir = scratch_dir("same");
+        let input = dir.join("a.txt");
+        let input_name = input.to_str().unwrap();
+        fs::write(&input, b"abracadabra").unwrap();
+
+        let args = format!("compress -f -o {}", input_name);
+
+        assert!(matches!(
+            commands::convert(&parse(&args), None, input_name),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!(fs::read(&input).unwrap(), b"abracadabra");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_convert_removes_partial_output() {
+        let dir = scratch_dir("partial");
+        let input = dir.join("a.txt.huff");
+        let input_name = input.to_str().unwrap();
+
+        let mut compressed = huffman_coding::encode(&[b'a'; 1000]).unwrap();
+        compressed.truncate(compressed.len() - 4);
+        fs::write(&input, &compressed).unwrap();
+
+        assert!(commands::convert(&parse("decompress"), None, input_name).is_err());
+        assert!(input.exists());
+        assert!(!dir.join("a.txt").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_file_removes_partial_output() {
+        let dir = scratch_dir("partial-table");
+        let path = dir.join("words.table");
+
+        let result = commands::write_file(&path, |writer| {
+            writer.write_all(b"HUFT")?;
+            writer.flush()?;
+            Err(Error::InvalidArgument("Failed half way"))
+        });
+
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        assert!(!path.exists());
+
+        commands::write_file(&path, |writer| Ok(writer.write_all(b"HUFT")?)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"HUFT");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
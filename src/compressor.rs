@@ -1,133 +1,266 @@
-use crate::huffman;
-use std::collections::HashMap;
-
+use crate::bit_writer::BitWriter;
+use crate::error::{Error, Result};
+use crate::huffman::{self, Symbol};
+use std::collections::{BinaryHeap, HashMap};
+use std::io::Write;
+
+pub mod context;
+pub mod histogram;
+pub mod live;
+pub mod stream;
 pub mod tree;
 
-pub fn calc_codes(root_node: &Box<huffman::Node>) -> HashMap<u8, huffman::HuffmanCode> {
-    let mut codes = HashMap::<u8, huffman::HuffmanCode>::new();
+pub fn calc_codes<S: Symbol>(
+    root_node: &huffman::Node<S>,
+) -> Result<HashMap<S, huffman::HuffmanCode>> {
+    codes_from_lengths(&calc_lengths(root_node)?)
+}
+
+// Fails with `Error::CodeTooLong` for trees deeper than `HuffmanCode::MAX_LENGTH`.
+pub fn calc_lengths<S: Symbol>(root_node: &huffman::Node<S>) -> Result<HashMap<S, u8>> {
+    // a lone symbol still needs a bit per occurrence to be counted by the decoder
+    if let Some(symbol) = root_node.symbol {
+        return Ok(HashMap::from([(symbol, 1)]));
+    }
+
+    let mut codes = HashMap::<S, huffman::HuffmanCode>::new();
     let mut current_code = huffman::HuffmanCode::new();
 
-    walk_tree(root_node, &mut codes, &mut current_code);
+    walk_tree(root_node, &mut codes, &mut current_code)?;
+
+    Ok(codes
+        .into_iter()
+        .map(|(symbol, code)| (symbol, code.bin_length()))
+        .collect())
+}
+
+// Code lengths of an optimal code for the given byte frequencies; the rare inputs whose optimal
+// code is too long get the best code within `HuffmanCode::MAX_LENGTH` instead.
+pub fn lengths_from_frequencies(frequencies: &[u64; 256]) -> Result<HashMap<u8, u8>> {
+    let mut frequencies = *frequencies;
+    scale_to_fit(frequencies.iter_mut());
+
+    limited_lengths(|| tree::from_frequencies(&frequencies))
+}
+
+// Same as `lengths_from_frequencies` for any symbol type.
+pub fn lengths_from_symbol_frequencies<S: Symbol>(
+    frequencies: &HashMap<S, u64>,
+) -> Result<HashMap<S, u8>> {
+    let mut frequencies = frequencies.clone();
+    scale_to_fit(frequencies.values_mut());
+
+    limited_lengths(|| tree::from_symbol_frequencies(&frequencies))
+}
+
+// Halves the frequencies until they add up to a `u64`, so the weights of the tree nodes cannot
+// overflow; a non-zero frequency stays non-zero.
+fn scale_to_fit<'a, I: Iterator<Item = &'a mut u64>>(frequencies: I) {
+    let mut frequencies: Vec<&mut u64> = frequencies.collect();
+
+    while frequencies
+        .iter()
+        .map(|frequency| **frequency as u128)
+        .sum::<u128>()
+        > u64::MAX as u128
+    {
+        for frequency in frequencies.iter_mut() {
+            **frequency = **frequency / 2 + **frequency % 2;
+        }
+    }
+}
+
+fn limited_lengths<S, F>(leaves: F) -> Result<HashMap<S, u8>>
+where
+    S: Symbol,
+    F: Fn() -> BinaryHeap<Box<huffman::Node<S>>>,
+{
+    let huffman_tree = match tree::build(leaves()) {
+        Some(root_node) => root_node,
+        None => return Ok(HashMap::new()),
+    };
+
+    match calc_lengths(&huffman_tree) {
+        Err(Error::CodeTooLong) => {
+            let limited_tree = tree::build_limited(leaves(), huffman::HuffmanCode::MAX_LENGTH)?;
+
+            match limited_tree {
+                Some(root_node) => calc_lengths(&root_node),
+                None => Ok(HashMap::new()),
+            }
+        }
+        result => result,
+    }
+}
+
+// Lengths of zero mean the symbol has no code.
+pub fn check_lengths<S: Symbol>(lengths: &HashMap<S, u8>) -> Result<()> {
+    let mut kraft_sum = 0u128;
 
-    codes
+    for length in lengths.values() {
+        if *length > huffman::HuffmanCode::MAX_LENGTH {
+            return Err(Error::CodeTooLong);
+        }
+
+        if *length > 0 {
+            kraft_sum += 1u128 << (huffman::HuffmanCode::MAX_LENGTH - length);
+        }
+    }
+
+    if kraft_sum > 1u128 << huffman::HuffmanCode::MAX_LENGTH {
+        return Err(Error::InvalidArgument(
+            "Code lengths do not form a prefix code",
+        ));
+    }
+
+    Ok(())
+}
+
+// Canonical assignment: shorter codes first, equal lengths ordered by symbol.
+pub fn codes_from_lengths<S: Symbol>(
+    lengths: &HashMap<S, u8>,
+) -> Result<HashMap<S, huffman::HuffmanCode>> {
+    check_lengths(lengths)?;
+
+    let mut sorted_lengths: Vec<(u8, S)> = lengths
+        .iter()
+        .filter(|(_, length)| **length > 0)
+        .map(|(symbol, length)| (*length, *symbol))
+        .collect();
+    sorted_lengths.sort();
+
+    let mut codes = HashMap::<S, huffman::HuffmanCode>::new();
+    let mut next_code = 0u64;
+    let mut prev_length = 0u8;
+
+    for (length, symbol) in sorted_lengths {
+        next_code = next_code
+            .checked_shl((length - prev_length) as u32)
+            .unwrap_or(0);
+        codes.insert(symbol, huffman::HuffmanCode::from_parts(next_code, length)?);
+
+        // only wraps after the last code of a complete 64-bit code
+        next_code = next_code.wrapping_add(1);
+        prev_length = length;
+    }
+
+    Ok(codes)
 }
 
-pub fn compress(bytes: &[u8], codes: &HashMap<u8, huffman::HuffmanCode>) -> (Vec<u8>, usize) {
-    let mut result = Vec::<u8>::new();
+pub fn compress(
+    bytes: &[u8],
+    codes: &HashMap<u8, huffman::HuffmanCode>,
+) -> Result<(Vec<u8>, usize)> {
+    let mut writer = BitWriter::new(Vec::<u8>::with_capacity(bytes.len()));
+
+    compress_into(bytes, codes, &mut writer)?;
 
-    let mut total_length = 0usize;
+    let total_length = writer.bit_len() as usize;
+
+    Ok((writer.finish()?, total_length))
+}
+
+pub fn compress_into<W: Write>(
+    bytes: &[u8],
+    codes: &HashMap<u8, huffman::HuffmanCode>,
+    writer: &mut BitWriter<W>,
+) -> Result<()> {
+    let mut code_table = [None; 256];
+
+    for (byte, code) in codes {
+        code_table[*byte as usize] = Some(*code);
+    }
 
     for byte in bytes {
-        let code = codes
-            .get(byte)
-            .expect("The tree must contain all possible variants.");
+        let code = code_table[*byte as usize]
+            .as_ref()
+            .ok_or_else(|| Error::symbol_not_in_table(byte))?;
 
-        let free_bits = (result.len() * 8 - total_length) as u8;
+        writer.write_code(code)?;
+    }
 
-        if free_bits == 0 {
-            let packed_byte = code.bin_repres() << (8 - code.bin_length());
-            result.push(packed_byte);
-        } else {
-            let tail = result.pop().expect("Broken compressed sequence.");
+    Ok(())
+}
 
-            if free_bits >= code.bin_length() {
-                let tail_filler = code.bin_repres() << (free_bits - code.bin_length());
-                let packed_byte = tail | tail_filler;
+// Counterparts of `compress`/`compress_into` for any symbol type; bytes are better served by
+// those, which look codes up in an array instead of a map.
+pub fn compress_symbols<S: Symbol>(
+    symbols: &[S],
+    codes: &HashMap<S, huffman::HuffmanCode>,
+) -> Result<(Vec<u8>, usize)> {
+    let mut writer = BitWriter::new(Vec::<u8>::new());
 
-                result.push(packed_byte);
-            } else {
-                let tail_filler = code.bin_repres() >> (code.bin_length() - free_bits);
-                let packed_byte = tail | tail_filler;
+    compress_symbols_into(symbols, codes, &mut writer)?;
 
-                result.push(packed_byte);
+    let total_length = writer.bit_len() as usize;
 
-                let new_tail = code.bin_repres() << (8 - (code.bin_length() - free_bits));
+    Ok((writer.finish()?, total_length))
+}
 
-                result.push(new_tail);
-            }
-        }
+pub fn compress_symbols_into<S: Symbol, W: Write>(
+    symbols: &[S],
+    codes: &HashMap<S, huffman::HuffmanCode>,
+    writer: &mut BitWriter<W>,
+) -> Result<()> {
+    for symbol in symbols {
+        let code = codes
+            .get(symbol)
+            .ok_or_else(|| Error::symbol_not_in_table(symbol))?;
 
-        total_length += code.bin_length() as usize;
+        writer.write_code(code)?;
     }
 
-    (result, total_length)
+    Ok(())
 }
 
-fn walk_tree(
-    node: &Box<huffman::Node>,
-    codes: &mut HashMap<u8, huffman::HuffmanCode>,
+fn walk_tree<S: Symbol>(
+    node: &huffman::Node<S>,
+    codes: &mut HashMap<S, huffman::HuffmanCode>,
     current_code: &mut huffman::HuffmanCode,
-) {
-    if let Some(byte) = node.byte {
-        codes.insert(byte, *current_code);
-        return;
+) -> Result<()> {
+    if let Some(symbol) = node.symbol {
+        codes.insert(symbol, *current_code);
+        return Ok(());
     }
 
     if let Some(left) = &node.left {
-        current_code.push_bit(false);
-        walk_tree(left, codes, current_code);
+        current_code.push_bit(false)?;
+        walk_tree(left, codes, current_code)?;
         current_code.pop_bit();
     }
 
     if let Some(right) = &node.right {
-        current_code.push_bit(true);
-        walk_tree(right, codes, current_code);
+        current_code.push_bit(true)?;
+        walk_tree(right, codes, current_code)?;
         current_code.pop_bit();
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{compressor, huffman};
-    use std::collections::BinaryHeap;
+    use crate::error::Error;
+    use crate::{compressor, decompressor, huffman};
+    use std::collections::{BinaryHeap, HashMap};
     use std::fs::File;
-    use std::io::Read;
+    use std::io::{Cursor, Read};
     use std::path::Path;
 
     fn initial_tree() -> BinaryHeap<Box<huffman::Node>> {
         let mut bottom_leaves = BinaryHeap::<Box<huffman::Node>>::new();
 
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 2,
-            byte: Some('A' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 2,
-            byte: Some('B' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 2,
-            byte: Some('C' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 3,
-            byte: Some('D' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 5,
-            byte: Some('F' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 10,
-            byte: Some('E' as u8),
-            left: None,
-            right: None,
-        }));
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'A', 2)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'B', 2)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'C', 2)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'D', 3)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'F', 5)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'E', 10)));
 
         bottom_leaves
     }
@@ -136,7 +269,8 @@ mod tests {
     fn test_compress() {
         let initial_tree = initial_tree();
         let huffman_tree = compressor::tree::build(initial_tree);
-        let codes = compressor::calc_codes(&huffman_tree.expect("No empty tree possible."));
+        let codes =
+            compressor::calc_codes(&huffman_tree.expect("No empty tree possible.")).unwrap();
 
         let file_in = File::open(Path::new("test-data/dataset_0/expected.txt"));
 
@@ -146,28 +280,176 @@ mod tests {
                 file.read_to_string(&mut str).unwrap();
                 str
             }
-            Err(_) => {
-                assert!(false, "Could not open a test dataset");
-                String::new()
-            }
+            Err(_) => panic!("Could not open a test dataset"),
         };
 
-        let res = compressor::compress(message.as_bytes(), &codes);
+        let res = compressor::compress(message.as_bytes(), &codes).unwrap();
 
         let expected = (
             vec![
-                0b00100010u8,
-                0b00000000,
+                0b11101110u8,
+                0b10101010,
+                0b10110011,
+                0b00110100,
                 0b00000000,
-                0b01100110,
+                0b11011111,
                 0b11111111,
-                0b11110110,
-                0b10010010,
-                0b10000000,
+                0b00000000,
             ],
             57usize,
         );
 
         assert_eq!(res, expected);
     }
+
+    #[test]
+    fn test_codes_from_lengths() {
+        let mut lengths = HashMap::new();
+        lengths.insert(b'A', 3);
+        lengths.insert(b'B', 2);
+        lengths.insert(b'C', 3);
+        lengths.insert(b'D', 2);
+        lengths.insert(b'E', 2);
+        lengths.insert(b'F', 0);
+
+        let codes = compressor::codes_from_lengths(&lengths).unwrap();
+
+        let mut actual: Vec<(u8, u64, u8)> = codes
+            .iter()
+            .map(|(byte, code)| (*byte, code.bin_repres(), code.bin_length()))
+            .collect();
+        actual.sort();
+
+        let expected = vec![
+            (b'A', 0b110, 3),
+            (b'B', 0b00, 2),
+            (b'C', 0b111, 3),
+            (b'D', 0b01, 2),
+            (b'E', 0b10, 2),
+        ];
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_compress_long_codes() {
+        let frequencies = compressor::tree::fibonacci_frequencies(24);
+        let message: Vec<u8> = (0..24u8)
+            .flat_map(|byte| std::iter::repeat_n(byte, frequencies[byte as usize] as usize))
+            .collect();
+
+        let mut reader = Cursor::new(&message);
+        let initial_tree = compressor::tree::init(&mut reader).unwrap();
+        let huffman_tree = compressor::tree::build(initial_tree).expect("No empty tree possible.");
+        let codes = compressor::calc_codes(&huffman_tree).unwrap();
+
+        let longest = codes.values().map(|code| code.bin_length()).max().unwrap();
+        assert_eq!(longest, 23);
+
+        let compressed = compressor::compress(&message, &codes).unwrap();
+        let expected_length: usize = message
+            .iter()
+            .map(|byte| codes[byte].bin_length() as usize)
+            .sum();
+
+        assert_eq!(compressed.1, expected_length);
+        assert_eq!(compressed.0.len(), expected_length.div_ceil(8));
+        let decoding_tree = compressor::tree::from_codes(&codes).unwrap().unwrap();
+        assert_eq!(
+            decompressor::decompress(&compressed, &decoding_tree).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_compress_single_symbol() {
+        let message = b"zzzzz".to_vec();
+
+        let initial_tree = compressor::tree::init(&mut Cursor::new(&message)).unwrap();
+        let huffman_tree = compressor::tree::build(initial_tree).unwrap();
+        let codes = compressor::calc_codes(&huffman_tree).unwrap();
+
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[&b'z'].bin_length(), 1);
+
+        let compressed = compressor::compress(&message, &codes).unwrap();
+        assert_eq!(compressed, (vec![0], 5));
+
+        let decoding_tree = compressor::tree::from_codes(&codes).unwrap().unwrap();
+        assert_eq!(
+            decompressor::decompress(&compressed, &decoding_tree).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_lengths_from_deep_frequencies() {
+        let frequencies = compressor::tree::fibonacci_frequencies(80);
+
+        let huffman_tree =
+            compressor::tree::build(compressor::tree::from_frequencies(&frequencies)).unwrap();
+        assert!(matches!(
+            compressor::calc_lengths(&huffman_tree),
+            Err(Error::CodeTooLong)
+        ));
+
+        let lengths = compressor::lengths_from_frequencies(&frequencies).unwrap();
+        assert_eq!(lengths.len(), 80);
+        assert_eq!(
+            lengths.values().max(),
+            Some(&huffman::HuffmanCode::MAX_LENGTH)
+        );
+    }
+
+    #[test]
+    fn test_compress_symbol_not_in_table() {
+        let codes = compressor::codes_from_lengths(&HashMap::from([(b'a', 1), (b'b', 1)])).unwrap();
+
+        assert!(matches!(
+            compressor::compress(b"abc", &codes),
+            Err(Error::SymbolNotInTable(symbol)) if symbol == "99"
+        ));
+        assert!(matches!(
+            compressor::codes_from_lengths(&HashMap::from([(b'a', 1), (b'b', 1), (b'c', 1)])),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_compress_symbols() {
+        // token IDs beyond the byte range, skewed towards the low ones
+        let message: Vec<u16> = (0..20_000u32)
+            .map(|index| (index * index % 1021 % (1 + index % 700)) as u16)
+            .collect();
+
+        let mut frequencies = HashMap::<u16, u64>::new();
+
+        for symbol in &message {
+            *frequencies.entry(*symbol).or_insert(0) += 1;
+        }
+
+        let lengths = compressor::lengths_from_symbol_frequencies(&frequencies).unwrap();
+        assert_eq!(lengths.len(), frequencies.len());
+        assert!(lengths.len() > 256);
+
+        let codes = compressor::codes_from_lengths(&lengths).unwrap();
+        let compressed = compressor::compress_symbols(&message, &codes).unwrap();
+
+        let decoding_tree = compressor::tree::from_codes(&codes).unwrap().unwrap();
+        assert_eq!(
+            decompressor::decompress(&compressed, &decoding_tree).unwrap(),
+            message
+        );
+
+        let table = decompressor::table::DecodeTable::new(&codes);
+        assert_eq!(
+            decompressor::table::decompress(&compressed, &table).unwrap(),
+            message
+        );
+
+        assert!(matches!(
+            compressor::compress_symbols(&[u16::MAX], &codes),
+            Err(Error::SymbolNotInTable(symbol)) if symbol == "65535"
+        ));
+    }
 }
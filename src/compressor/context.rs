@@ -0,0 +1,208 @@
+use crate::bit_writer::BitWriter;
+use crate::error::{Error, Result};
+use crate::{compressor, container, huffman};
+use std::collections::HashMap;
+use std::io::Write;
+
+// The first byte of a block is coded as if preceded by this one.
+pub const INITIAL_CONTEXT: u8 = 0;
+
+// Code tables conditioned on the preceding byte. Contexts too rare to pay for a table of their
+// own are coded with a table shared between all of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextTables {
+    pub shared: HashMap<u8, u8>,
+    // indexed by the preceding byte, `None` falls back to `shared`
+    pub by_context: Vec<Option<HashMap<u8, u8>>>,
+}
+
+impl ContextTables {
+    // Gives a context its own table only where that saves more bits than the table costs.
+    pub fn from_block(block: &[u8]) -> Result<ContextTables> {
+        let frequencies = context_frequencies(block);
+
+        let mut order0_frequencies = [0u64; 256];
+
+        for context_frequencies in &frequencies {
+            add_frequencies(&mut order0_frequencies, context_frequencies);
+        }
+
+        let order0_lengths = compressor::lengths_from_frequencies(&order0_frequencies)?;
+
+        let mut by_context = Vec::with_capacity(256);
+        let mut shared_frequencies = [0u64; 256];
+
+        for context_frequencies in &frequencies {
+            let own_lengths = compressor::lengths_from_frequencies(context_frequencies)?;
+
+            let own_cost =
+                coded_len(context_frequencies, &own_lengths) + 8 * table_len(&own_lengths)? as u64;
+
+            if !own_lengths.is_empty() && own_cost < coded_len(context_frequencies, &order0_lengths)
+            {
+                by_context.push(Some(own_lengths));
+            } else {
+                add_frequencies(&mut shared_frequencies, context_frequencies);
+                by_context.push(None);
+            }
+        }
+
+        Ok(ContextTables {
+            shared: compressor::lengths_from_frequencies(&shared_frequencies)?,
+            by_context,
+        })
+    }
+
+    pub fn lengths(&self, context: u8) -> &HashMap<u8, u8> {
+        self.by_context[context as usize]
+            .as_ref()
+            .unwrap_or(&self.shared)
+    }
+
+    pub fn own_table_count(&self) -> usize {
+        self.by_context
+            .iter()
+            .filter(|table| table.is_some())
+            .count()
+    }
+
+    // Length of the payload `compress_into` writes for `block`.
+    pub fn bit_len(&self, block: &[u8]) -> u64 {
+        let mut context = INITIAL_CONTEXT;
+        let mut bit_len = 0u64;
+
+        for byte in block {
+            bit_len += *self.lengths(context).get(byte).unwrap_or(&0) as u64;
+            context = *byte;
+        }
+
+        bit_len
+    }
+
+    // Canonical codes of every context, indexed by the preceding byte.
+    pub fn codes(&self) -> Result<Vec<HashMap<u8, huffman::HuffmanCode>>> {
+        let shared_codes = compressor::codes_from_lengths(&self.shared)?;
+
+        self.by_context
+            .iter()
+            .map(|table| match table {
+                Some(lengths) => compressor::codes_from_lengths(lengths),
+                None => Ok(shared_codes.clone()),
+            })
+            .collect()
+    }
+}
+
+// Byte frequencies of `block` indexed by the preceding byte.
+pub fn context_frequencies(block: &[u8]) -> Vec<[u64; 256]> {
+    let mut frequencies = vec![[0u64; 256]; 256];
+    let mut context = INITIAL_CONTEXT;
+
+    for byte in block {
+        frequencies[context as usize][*byte as usize] += 1;
+        context = *byte;
+    }
+
+    frequencies
+}
+
+pub fn compress_into<W: Write>(
+    block: &[u8],
+    tables: &ContextTables,
+    writer: &mut BitWriter<W>,
+) -> Result<()> {
+    let codes = tables.codes()?;
+    let mut context = INITIAL_CONTEXT;
+
+    for byte in block {
+        let code = codes[context as usize]
+            .get(byte)
+            .ok_or_else(|| Error::symbol_not_in_table(byte))?;
+
+        writer.write_code(code)?;
+        context = *byte;
+    }
+
+    Ok(())
+}
+
+fn add_frequencies(total: &mut [u64; 256], frequencies: &[u64; 256]) {
+    for (total, frequency) in total.iter_mut().zip(frequencies) {
+        *total += frequency;
+    }
+}
+
+fn coded_len(frequencies: &[u64; 256], lengths: &HashMap<u8, u8>) -> u64 {
+    lengths
+        .iter()
+        .map(|(byte, length)| frequencies[*byte as usize] * *length as u64)
+        .sum()
+}
+
+// Bytes taken by the code lengths of a table in a block header.
+fn table_len(lengths: &HashMap<u8, u8>) -> Result<usize> {
+    let mut encoded = Vec::new();
+    container::write_code_lengths(&mut encoded, lengths)?;
+
+    Ok(encoded.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compressor;
+    use crate::compressor::context::{self, ContextTables};
+
+    #[test]
+    fn test_context_frequencies() {
+        let frequencies = context::context_frequencies(b"abab");
+
+        assert_eq!(
+            frequencies[context::INITIAL_CONTEXT as usize][b'a' as usize],
+            1
+        );
+        assert_eq!(frequencies[b'a' as usize][b'b' as usize], 2);
+        assert_eq!(frequencies[b'b' as usize][b'a' as usize], 1);
+        assert_eq!(frequencies.iter().flatten().sum::<u64>(), 4);
+    }
+
+    #[test]
+    fn test_tables_from_block() {
+        // "q" is always followed by "u", everything else is mixed
+        let message: Vec<u8> = b"quick quiet queue quota aqua "
+            .iter()
+            .copied()
+            .cycle()
+            .take(29 * 200)
+            .collect();
+
+        let tables = ContextTables::from_block(&message).unwrap();
+
+        let q_lengths = tables.by_context[b'q' as usize].as_ref().unwrap();
+        assert_eq!(q_lengths.len(), 1);
+        assert_eq!(tables.lengths(b'q')[&b'u'], 1);
+
+        // contexts that never occur have nothing to pay for
+        assert!(tables.by_context[b'z' as usize].is_none());
+
+        let mut frequencies = [0u64; 256];
+
+        for byte in &message {
+            frequencies[*byte as usize] += 1;
+        }
+
+        let order0_lengths = compressor::lengths_from_frequencies(&frequencies).unwrap();
+        let order0_len: u64 = message.iter().map(|byte| order0_lengths[byte] as u64).sum();
+
+        assert!(tables.bit_len(&message) < order0_len / 2);
+    }
+
+    #[test]
+    fn test_tables_rare_contexts() {
+        let message: Vec<u8> = (0..=255u8).collect();
+        let tables = ContextTables::from_block(&message).unwrap();
+
+        assert_eq!(tables.own_table_count(), 0);
+        assert_eq!(tables.shared.len(), 256);
+        assert_eq!(tables.bit_len(&message), 256 * 8);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::error::Result;
+use std::io::{ErrorKind, Read};
+
+// Every lane counts at most this many bytes before being added to the totals, so lane counters
+// of 32 bits cannot overflow.
+const FLUSH_SIZE: usize = 1 << 30;
+const READ_SIZE: usize = 256 * 1024;
+const LANES: usize = 4;
+
+// Adds the byte frequencies of `bytes` to `frequencies`.
+//
+// Consecutive bytes go to separate tables: runs of equal bytes would otherwise make every
+// increment wait for the store of the previous one.
+pub fn count(bytes: &[u8], frequencies: &mut [u64; 256]) {
+    let mut lanes = [[0u32; 256]; LANES];
+
+    for part in bytes.chunks(FLUSH_SIZE) {
+        let mut quads = part.chunks_exact(LANES);
+
+        for quad in &mut quads {
+            lanes[0][quad[0] as usize] += 1;
+            lanes[1][quad[1] as usize] += 1;
+            lanes[2][quad[2] as usize] += 1;
+            lanes[3][quad[3] as usize] += 1;
+        }
+
+        for byte in quads.remainder() {
+            lanes[0][*byte as usize] += 1;
+        }
+
+        for lane in lanes.iter_mut() {
+            for (total, count) in frequencies.iter_mut().zip(lane.iter_mut()) {
+                *total += *count as u64;
+                *count = 0;
+            }
+        }
+    }
+}
+
+// Counts everything left in `reader` into `frequencies` and returns the number of bytes read.
+pub fn count_reader<R: Read>(reader: &mut R, frequencies: &mut [u64; 256]) -> Result<u64> {
+    let mut buf = vec![0u8; READ_SIZE];
+    let mut total_len = 0u64;
+
+    loop {
+        let read_len = match reader.read(&mut buf) {
+            Ok(0) => return Ok(total_len),
+            Ok(read_len) => read_len,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        count(&buf[..read_len], frequencies);
+        total_len += read_len as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compressor::histogram;
+
+    fn naive(bytes: &[u8]) -> [u64; 256] {
+        let mut frequencies = [0u64; 256];
+
+        for byte in bytes {
+            frequencies[*byte as usize] += 1;
+        }
+
+        frequencies
+    }
+
+    #[test]
+    fn test_count() {
+        let message: Vec<u8> = (0..100_003u32)
+            .map(|index| (index * 7919 % 251) as u8 / ((index % 5) as u8 + 1))
+            .collect();
+
+        // every split between the lanes and the remainder
+        for len in [0, 1, 3, 4, 5, 1000, message.len()] {
+            let mut frequencies = [0u64; 256];
+            histogram::count(&message[..len], &mut frequencies);
+
+            assert_eq!(frequencies, naive(&message[..len]));
+        }
+
+        let mut frequencies = [1u64; 256];
+        histogram::count(&[9u8; 40], &mut frequencies);
+
+        assert_eq!(frequencies[9], 41);
+        assert_eq!(frequencies.iter().sum::<u64>(), 256 + 40);
+    }
+
+    #[test]
+    fn test_count_reader() {
+        let message: Vec<u8> = (0..600_000u32).map(|index| (index % 13) as u8).collect();
+
+        let mut frequencies = [0u64; 256];
+        let read_len = histogram::count_reader(&mut message.as_slice(), &mut frequencies).unwrap();
+
+        assert_eq!(read_len, message.len() as u64);
+        assert_eq!(frequencies, naive(&message));
+    }
+}
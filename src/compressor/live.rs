@@ -0,0 +1,114 @@
+use crate::bit_writer::BitWriter;
+use crate::checksum::Crc32;
+use crate::container;
+use crate::error::Result;
+use crate::vitter::VitterModel;
+use std::io::Write;
+
+// Codes bytes with Vitter's adaptive codes as they are written: nothing is buffered, counted
+// upfront or sent as a table, and `flush` makes every byte written so far decodable, so the
+// output can follow a pipe or a socket. `finish` must be called to terminate the stream.
+pub struct LiveEncoder<W: Write> {
+    writer: BitWriter<W>,
+    model: Box<VitterModel>,
+    checksum: Crc32,
+}
+
+impl<W: Write> LiveEncoder<W> {
+    pub fn new(mut writer: W) -> Result<LiveEncoder<W>> {
+        container::write_file_header(&mut writer)?;
+        container::write_block_header(
+            &mut writer,
+            &container::BlockHeader {
+                kind: container::BlockKind::Live,
+                original_len: 0,
+                bit_len: 0,
+                checksum: 0,
+            },
+        )?;
+
+        Ok(LiveEncoder {
+            writer: BitWriter::new(writer),
+            model: Box::new(VitterModel::new(container::LIVE_SYMBOL_COUNT)),
+            checksum: Crc32::new(),
+        })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.get_ref()
+    }
+
+    // Writes out the end of the live stream and of the container.
+    pub fn finish(mut self) -> Result<W> {
+        self.model.encode(container::LIVE_END, &mut self.writer)?;
+        self.writer.align()?;
+
+        let mut writer = self.writer.finish()?;
+        writer.write_all(&self.checksum.finish().to_le_bytes())?;
+        container::write_end(&mut writer)?;
+        writer.flush()?;
+
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for LiveEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for byte in buf {
+            self.model.encode(*byte as u16, &mut self.writer)?;
+        }
+
+        self.checksum.update(buf);
+
+        Ok(buf.len())
+    }
+
+    // Costs a flush symbol and the padding to the next byte.
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.model.encode(container::LIVE_FLUSH, &mut self.writer)?;
+        self.writer.align()?;
+
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compressor::live::LiveEncoder;
+    use crate::container;
+    use std::io::Write;
+
+    #[test]
+    fn test_write() {
+        let mut encoder = LiveEncoder::new(Vec::new()).unwrap();
+
+        // the file and block headers go out before any data
+        let mut header = b"HUFF\x06\x06".to_vec();
+        header.extend_from_slice(&crate::checksum::crc32(&[6]).to_le_bytes());
+        assert_eq!(encoder.get_ref(), &header);
+
+        encoder.write_all(b"abc").unwrap();
+        encoder.flush().unwrap();
+
+        // the first bytes in 9 bits each, the flush symbol after the NYT code "000" and padding
+        let flushed_len = encoder.get_ref().len();
+        assert_eq!(
+            flushed_len,
+            header.len() + (9 + 10 + 11 + 12u64).div_ceil(8) as usize
+        );
+
+        encoder.write_all(b"abc").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(
+            &compressed[compressed.len() - 5..compressed.len() - 1],
+            crate::checksum::crc32(b"abcabc").to_le_bytes()
+        );
+        assert_eq!(compressed[compressed.len() - 1], 0);
+
+        let mut reader = &compressed[5..];
+        let header = container::read_block_header(&mut reader).unwrap().unwrap();
+
+        assert!(matches!(header.kind, container::BlockKind::Live));
+    }
+}
@@ -0,0 +1,506 @@
+use crate::bit_writer::BitWriter;
+use crate::codebook::CodeBook;
+use crate::compressor::context;
+use crate::error::{Error, Result};
+use crate::shared_table::SharedTable;
+use crate::vitter::VitterModel;
+use crate::{checksum, compressor, container};
+use std::collections::HashMap;
+use std::io::Write;
+
+pub const DEFAULT_BLOCK_SIZE: usize = 1024 * 1024;
+// the range accepted from users, smaller blocks are dominated by their code tables
+pub const MIN_BLOCK_SIZE: usize = 64 * 1024;
+pub const MAX_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+enum Coding {
+    // every block gets a code table built from its own frequencies
+    PerBlock,
+    // every block is coded with the same codes
+    Fixed(CodeBook),
+    Adaptive(Box<VitterModel>),
+    // every block gets code tables conditioned on the preceding byte
+    Context,
+    // every block is coded with a table the decoder is given separately
+    Shared { table_id: u32, code_book: CodeBook },
+}
+
+// Buffers up to a block of input and writes it out with its own code table, so memory use
+// stays bounded by the block size. `finish` must be called to terminate the stream.
+pub struct HuffmanEncoder<W: Write> {
+    writer: W,
+    coding: Coding,
+    block: Vec<u8>,
+    block_size: usize,
+    header_written: bool,
+}
+
+impl<W: Write> HuffmanEncoder<W> {
+    pub fn new(writer: W) -> HuffmanEncoder<W> {
+        HuffmanEncoder::with_coding(writer, Coding::PerBlock, DEFAULT_BLOCK_SIZE)
+    }
+
+    // `block_size` must lie within `MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE`.
+    pub fn with_block_size(writer: W, block_size: usize) -> Result<HuffmanEncoder<W>> {
+        if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+            return Err(Error::InvalidArgument(
+                "Block size must be between 64 KiB and 4 MiB",
+            ));
+        }
+
+        HuffmanEncoder::with_any_block_size(writer, block_size)
+    }
+
+    // Lets tests cut their messages into blocks of a few bytes.
+    pub(crate) fn with_any_block_size(writer: W, block_size: usize) -> Result<HuffmanEncoder<W>> {
+        if block_size == 0 || block_size > MAX_BLOCK_SIZE {
+            return Err(Error::InvalidArgument(
+                "Block size must not be zero or above 4 MiB",
+            ));
+        }
+
+        Ok(HuffmanEncoder::with_coding(
+            writer,
+            Coding::PerBlock,
+            block_size,
+        ))
+    }
+
+    // Codes all blocks with the given lengths; writing a byte without a code fails with
+    // `Error::SymbolNotInTable`.
+    pub fn with_code_lengths(
+        writer: W,
+        code_lengths: HashMap<u8, u8>,
+    ) -> Result<HuffmanEncoder<W>> {
+        Ok(HuffmanEncoder::with_coding(
+            writer,
+            Coding::Fixed(CodeBook::from_lengths(code_lengths)?),
+            DEFAULT_BLOCK_SIZE,
+        ))
+    }
+
+    // Codes blocks with a single adaptive model, so no code tables are sent and nothing has
+    // to be known about the input upfront.
+    pub fn adaptive(writer: W) -> HuffmanEncoder<W> {
+        HuffmanEncoder::with_coding(
+            writer,
+            Coding::Adaptive(Box::new(VitterModel::new(container::ADAPTIVE_SYMBOL_COUNT))),
+            DEFAULT_BLOCK_SIZE,
+        )
+    }
+
+    // Codes every block with a table per preceding byte where that pays off, which suits text
+    // with strongly correlated neighbouring bytes at the cost of slower compression.
+    pub fn with_contexts(writer: W) -> HuffmanEncoder<W> {
+        HuffmanEncoder::with_coding(writer, Coding::Context, DEFAULT_BLOCK_SIZE)
+    }
+
+    // Codes all blocks with a pre-trained table, which the blocks refer to by its ID instead of
+    // carrying it; the decoder needs the same table.
+    pub fn with_shared_table(writer: W, table: &SharedTable) -> HuffmanEncoder<W> {
+        HuffmanEncoder::with_coding(
+            writer,
+            Coding::Shared {
+                table_id: table.id,
+                code_book: table.code_book.clone(),
+            },
+            DEFAULT_BLOCK_SIZE,
+        )
+    }
+
+    fn with_coding(writer: W, coding: Coding, block_size: usize) -> HuffmanEncoder<W> {
+        HuffmanEncoder {
+            writer,
+            coding,
+            block: Vec::with_capacity(std::cmp::min(block_size, MAX_BLOCK_SIZE)),
+            block_size,
+            header_written: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    // Writes out the buffered block and the end of the stream.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush_block()?;
+        container::write_end(&mut self.writer)?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        if !self.header_written {
+            container::write_file_header(&mut self.writer)?;
+            self.header_written = true;
+        }
+
+        if self.block.is_empty() {
+            return Ok(());
+        }
+
+        match &mut self.coding {
+            Coding::PerBlock => {
+                let frequencies = byte_frequencies(&self.block);
+                let code_book = CodeBook::from_frequencies(&frequencies)?;
+                write_block(
+                    &mut self.writer,
+                    &self.block,
+                    &frequencies,
+                    &code_book,
+                    None,
+                )?;
+            }
+            Coding::Fixed(code_book) => {
+                let frequencies = byte_frequencies(&self.block);
+                write_block(&mut self.writer, &self.block, &frequencies, code_book, None)?;
+            }
+            Coding::Shared {
+                table_id,
+                code_book,
+            } => {
+                let frequencies = byte_frequencies(&self.block);
+                write_block(
+                    &mut self.writer,
+                    &self.block,
+                    &frequencies,
+                    code_book,
+                    Some(*table_id),
+                )?;
+            }
+            Coding::Adaptive(model) => write_adaptive_block(&mut self.writer, &self.block, model)?,
+            Coding::Context => write_context_block(&mut self.writer, &self.block)?,
+        }
+
+        self.block.clear();
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for HuffmanEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.block.len() == self.block_size {
+            self.flush_block()?;
+        }
+
+        let taken = std::cmp::min(buf.len(), self.block_size - self.block.len());
+        self.block.extend_from_slice(&buf[..taken]);
+
+        Ok(taken)
+    }
+
+    // Closes the current block early, everything written so far becomes decodable.
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flush_block()?;
+        self.writer.flush()
+    }
+}
+
+fn byte_frequencies(block: &[u8]) -> [u64; 256] {
+    let mut frequencies = [0u64; 256];
+    compressor::histogram::count(block, &mut frequencies);
+
+    frequencies
+}
+
+// `frequencies` are the byte counts of `block`. The code lengths go into the block header unless
+// they belong to the shared table `table_id`.
+fn write_block<W: Write>(
+    writer: &mut W,
+    block: &[u8],
+    frequencies: &[u64; 256],
+    code_book: &CodeBook,
+    table_id: Option<u32>,
+) -> Result<()> {
+    let (code_lengths, codes) = (code_book.code_lengths(), code_book.codes());
+
+    // checked upfront so that nothing of a failing block is written
+    if let Some(byte) =
+        (0..=255u8).find(|byte| frequencies[*byte as usize] > 0 && !codes.contains_key(byte))
+    {
+        return Err(Error::symbol_not_in_table(&byte));
+    }
+
+    let bit_len = code_lengths
+        .iter()
+        .map(|(byte, length)| frequencies[*byte as usize] * *length as u64)
+        .sum();
+
+    let kind = match table_id {
+        Some(table_id) => container::BlockKind::Shared { table_id },
+        None => container::BlockKind::Huffman {
+            code_lengths: code_lengths.clone(),
+        },
+    };
+
+    let header = container::BlockHeader {
+        kind,
+        original_len: block.len() as u64,
+        bit_len,
+        checksum: checksum::crc32(block),
+    };
+
+    let mut encoded_header = Vec::new();
+    container::write_block_header(&mut encoded_header, &header)?;
+
+    if expands(block, encoded_header.len() as u64 + bit_len.div_ceil(8)) {
+        return write_stored_block(writer, block, header.checksum);
+    }
+
+    writer.write_all(&encoded_header)?;
+
+    let mut bit_writer = BitWriter::new(writer);
+    compressor::compress_into(block, codes, &mut bit_writer)?;
+    bit_writer.finish()?;
+
+    Ok(())
+}
+
+fn write_context_block<W: Write>(writer: &mut W, block: &[u8]) -> Result<()> {
+    let tables = context::ContextTables::from_block(block)?;
+
+    // without any table of its own the context bitmap is wasted
+    if tables.own_table_count() == 0 {
+        let code_book = CodeBook::from_lengths(tables.shared)?;
+        return write_block(writer, block, &byte_frequencies(block), &code_book, None);
+    }
+
+    let header = container::BlockHeader {
+        original_len: block.len() as u64,
+        bit_len: tables.bit_len(block),
+        checksum: checksum::crc32(block),
+        kind: container::BlockKind::Context {
+            tables: tables.clone(),
+        },
+    };
+
+    let mut encoded_header = Vec::new();
+    container::write_block_header(&mut encoded_header, &header)?;
+
+    if expands(
+        block,
+        encoded_header.len() as u64 + header.bit_len.div_ceil(8),
+    ) {
+        return write_stored_block(writer, block, header.checksum);
+    }
+
+    writer.write_all(&encoded_header)?;
+
+    let mut bit_writer = BitWriter::new(writer);
+    context::compress_into(block, &tables, &mut bit_writer)?;
+    bit_writer.finish()?;
+
+    Ok(())
+}
+
+// The bit length is only known after coding, so the payload goes through memory first.
+// Stored blocks leave the model untouched on both sides.
+fn write_adaptive_block<W: Write>(
+    writer: &mut W,
+    block: &[u8],
+    model: &mut VitterModel,
+) -> Result<()> {
+    let previous_model = model.clone();
+    let mut bit_writer = BitWriter::new(Vec::new());
+
+    for byte in block {
+        model.encode(*byte as u16, &mut bit_writer)?;
+    }
+
+    let header = container::BlockHeader {
+        kind: container::BlockKind::Adaptive,
+        original_len: block.len() as u64,
+        bit_len: bit_writer.bit_len(),
+        checksum: checksum::crc32(block),
+    };
+
+    let mut encoded_header = Vec::new();
+    container::write_block_header(&mut encoded_header, &header)?;
+
+    if expands(
+        block,
+        encoded_header.len() as u64 + header.bit_len.div_ceil(8),
+    ) {
+        *model = previous_model;
+        return write_stored_block(writer, block, header.checksum);
+    }
+
+    writer.write_all(&encoded_header)?;
+    writer.write_all(&bit_writer.finish()?)?;
+
+    Ok(())
+}
+
+fn expands(block: &[u8], encoded_len: u64) -> bool {
+    encoded_len >= (container::STORED_OVERHEAD + block.len()) as u64
+}
+
+fn write_stored_block<W: Write>(writer: &mut W, block: &[u8], checksum: u32) -> Result<()> {
+    let header = container::BlockHeader {
+        kind: container::BlockKind::Stored,
+        original_len: block.len() as u64,
+        bit_len: block.len() as u64 * 8,
+        checksum,
+    };
+
+    container::write_block_header(writer, &header)?;
+    writer.write_all(block)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compressor::stream::{HuffmanEncoder, MAX_BLOCK_SIZE};
+    use crate::container;
+    use crate::error::Error;
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    #[test]
+    fn test_blocks() {
+        let message: Vec<u8> = b"abracadabra".iter().copied().cycle().take(25).collect();
+
+        let mut encoder = HuffmanEncoder::with_any_block_size(Vec::new(), 10).unwrap();
+        encoder.write_all(&message[..7]).unwrap();
+        encoder.write_all(&message[7..]).unwrap();
+
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = compressed.as_slice();
+        container::read_file_header(&mut reader).unwrap();
+
+        let mut block_lengths = Vec::new();
+
+        while let Some(header) = container::read_block_header(&mut reader).unwrap() {
+            block_lengths.push(header.original_len);
+            container::read_payload(&mut reader, &header).unwrap();
+        }
+
+        assert_eq!(block_lengths, vec![10, 10, 5]);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut encoder = HuffmanEncoder::new(Vec::new());
+
+        encoder.write_all(b"first").unwrap();
+        encoder.flush().unwrap();
+        let flushed_len = encoder.get_ref().len();
+
+        encoder.flush().unwrap();
+        assert_eq!(encoder.get_ref().len(), flushed_len);
+
+        encoder.write_all(b"second").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = &compressed[5..];
+        let first = container::read_block_header(&mut reader).unwrap().unwrap();
+        container::read_payload(&mut reader, &first).unwrap();
+
+        assert_eq!(first.original_len, 5);
+        assert_eq!(compressed.len() - reader.len(), flushed_len);
+    }
+
+    #[test]
+    fn test_fixed_code_lengths() {
+        let code_lengths: HashMap<u8, u8> = (0..4u8).map(|byte| (byte, 2)).collect();
+
+        let mut encoder =
+            HuffmanEncoder::with_code_lengths(Vec::new(), code_lengths.clone()).unwrap();
+        encoder.write_all(&[3; 100]).unwrap();
+        encoder.flush().unwrap();
+        encoder.write_all(&[1, 2].repeat(30)).unwrap();
+
+        let compressed = encoder.finish().unwrap();
+        let mut reader = &compressed[5..];
+
+        for expected_len in [100, 60] {
+            let header = container::read_block_header(&mut reader).unwrap().unwrap();
+
+            match &header.kind {
+                container::BlockKind::Huffman {
+                    code_lengths: block_lengths,
+                } => assert_eq!(block_lengths, &code_lengths),
+                _ => panic!("A Huffman block is expected"),
+            }
+
+            assert_eq!(header.original_len, expected_len);
+            assert_eq!(header.bit_len, expected_len * 2);
+            container::read_payload(&mut reader, &header).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fixed_code_lengths_missing_symbol() {
+        let code_lengths = HashMap::from([(b'a', 1), (b'b', 1)]);
+
+        let mut encoder = HuffmanEncoder::with_code_lengths(Vec::new(), code_lengths).unwrap();
+        encoder.write_all(b"abc").unwrap();
+
+        assert!(matches!(
+            encoder.finish(),
+            Err(Error::SymbolNotInTable(symbol)) if symbol == "99"
+        ));
+
+        for block_size in [0, 10, MAX_BLOCK_SIZE + 1, usize::MAX / 2] {
+            assert!(matches!(
+                HuffmanEncoder::with_block_size(Vec::new(), block_size),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_adaptive_blocks() {
+        let mut encoder = HuffmanEncoder::adaptive(Vec::new());
+        encoder.write_all(&[b'a'; 100]).unwrap();
+
+        let compressed = encoder.finish().unwrap();
+        let mut reader = &compressed[5..];
+
+        // the first "a" as is, then a single bit each
+        let header = container::read_block_header(&mut reader).unwrap().unwrap();
+        assert!(matches!(header.kind, container::BlockKind::Adaptive));
+        assert_eq!(header.original_len, 100);
+        assert_eq!(header.bit_len, 8 + 99);
+    }
+
+    #[test]
+    fn test_stored_when_expanding() {
+        let message: Vec<u8> = (0..=255u8).collect();
+
+        for mut encoder in [
+            HuffmanEncoder::new(Vec::new()),
+            HuffmanEncoder::adaptive(Vec::new()),
+        ] {
+            encoder.write_all(&message).unwrap();
+            let compressed = encoder.finish().unwrap();
+
+            assert_eq!(
+                compressed.len(),
+                5 + container::STORED_OVERHEAD + message.len() + 1
+            );
+
+            let mut reader = &compressed[5..];
+            let header = container::read_block_header(&mut reader).unwrap().unwrap();
+
+            assert!(matches!(header.kind, container::BlockKind::Stored));
+            assert_eq!(
+                container::read_payload(&mut reader, &header).unwrap(),
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let compressed = HuffmanEncoder::new(Vec::new()).finish().unwrap();
+
+        assert_eq!(compressed, b"HUFF\x06\x00".to_vec());
+    }
+}
@@ -1,75 +1,282 @@
-use crate::{huffman, stream_helpers};
+use crate::error::{Error, Result};
+use crate::huffman::Symbol;
+use crate::{compressor, huffman};
 use std::collections::{BinaryHeap, HashMap};
-use std::io::{Read, Seek};
+use std::io::Read;
 
-pub fn init<T: Read + Seek>(reader: &mut T) -> std::io::Result<BinaryHeap<Box<huffman::Node>>> {
-    let mut byte = [0u8];
-    let stream_len = stream_helpers::stream_length(reader)?;
+// Leaves of the bytes left in `reader`.
+pub fn init<T: Read>(reader: &mut T) -> Result<BinaryHeap<Box<huffman::Node>>> {
+    let mut frequencies = [0u64; 256];
+    compressor::histogram::count_reader(reader, &mut frequencies)?;
 
-    let mut frequencies = HashMap::<u8, u64>::new();
+    Ok(from_frequencies(&frequencies))
+}
 
-    while stream_helpers::stream_current_position(reader)? != stream_len {
-        reader.read_exact(&mut byte)?;
+pub fn from_frequencies(frequencies: &[u64; 256]) -> BinaryHeap<Box<huffman::Node>> {
+    (0..=255u8)
+        .filter(|byte| frequencies[*byte as usize] > 0)
+        .map(|byte| Box::new(huffman::Node::new_leaf(byte, frequencies[byte as usize])))
+        .collect()
+}
 
-        match frequencies.get_mut(&byte[0]) {
-            Some(frequency) => *frequency += 1,
-            None => {
-                frequencies.insert(byte[0], 1);
-                ()
-            }
+// Leaves of the symbols with a non-zero frequency.
+pub fn from_symbol_frequencies<S: Symbol>(
+    frequencies: &HashMap<S, u64>,
+) -> BinaryHeap<Box<huffman::Node<S>>> {
+    frequencies
+        .iter()
+        .filter(|(_, frequency)| **frequency > 0)
+        .map(|(symbol, frequency)| Box::new(huffman::Node::new_leaf(*symbol, *frequency)))
+        .collect()
+}
+
+pub fn build<S: Symbol>(
+    mut bin_heap: BinaryHeap<Box<huffman::Node<S>>>,
+) -> Option<Box<huffman::Node<S>>> {
+    while bin_heap.len() > 1 {
+        let right_node = bin_heap.pop().unwrap();
+        let left_node = bin_heap.pop().unwrap();
+
+        bin_heap.push(Box::new(huffman::Node::new_joined(left_node, right_node)));
+    }
+
+    bin_heap.pop()
+}
+
+// Package-merge: the optimal prefix code whose words are at most `max_len` bits long.
+pub fn build_limited<S: Symbol>(
+    bin_heap: BinaryHeap<Box<huffman::Node<S>>>,
+    max_len: u8,
+) -> Result<Option<Box<huffman::Node<S>>>> {
+    let mut leaves = Vec::<(u64, S)>::with_capacity(bin_heap.len());
+
+    for node in bin_heap {
+        let symbol = node
+            .symbol
+            .ok_or(Error::InvalidArgument("Only leaves can be limited"))?;
+
+        leaves.push((node.frequency, symbol));
+    }
+
+    leaves.sort();
+
+    if max_len > huffman::HuffmanCode::MAX_LENGTH {
+        return Err(Error::CodeTooLong);
+    }
+
+    if leaves.len() as u128 > 1u128 << max_len {
+        return Err(Error::InvalidArgument(
+            "Too many symbols for the code length limit",
+        ));
+    }
+
+    if leaves.len() < 2 {
+        return Ok(build(
+            leaves
+                .into_iter()
+                .map(|(frequency, symbol)| Box::new(huffman::Node::new_leaf(symbol, frequency)))
+                .collect(),
+        ));
+    }
+
+    let leaf_items: Vec<(u64, Vec<usize>)> = leaves
+        .iter()
+        .enumerate()
+        .map(|(index, leaf)| (leaf.0, vec![index]))
+        .collect();
+
+    let mut row = leaf_items.clone();
+
+    for _ in 1..max_len {
+        let packages = row
+            .chunks_exact(2)
+            .map(|pair| {
+                let mut merged = pair[0].1.clone();
+                merged.extend_from_slice(&pair[1].1);
+                (pair[0].0 + pair[1].0, merged)
+            })
+            .collect();
+
+        row = merge_items(leaf_items.clone(), packages);
+    }
+
+    let mut lengths = HashMap::<S, u8>::new();
+
+    for item in row.iter().take(2 * leaves.len() - 2) {
+        for index in &item.1 {
+            *lengths.entry(leaves[*index].1).or_insert(0) += 1;
         }
     }
 
-    let mut result = BinaryHeap::<Box<huffman::Node>>::new();
+    let codes = compressor::codes_from_lengths(&lengths)?;
+    let mut root = match from_codes(&codes)? {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+
+    let frequencies: HashMap<S, u64> = leaves
+        .iter()
+        .map(|(frequency, symbol)| (*symbol, *frequency))
+        .collect();
+    assign_frequencies(&mut root, &frequencies);
+
+    Ok(Some(root))
+}
+
+// Total number of bits the tree spends on the symbols it was built from.
+pub fn cost<S: Symbol>(root_node: &huffman::Node<S>) -> u64 {
+    leaves_cost(root_node, 0)
+}
+
+pub fn from_codes<S: Symbol>(
+    codes: &HashMap<S, huffman::HuffmanCode>,
+) -> Result<Option<Box<huffman::Node<S>>>> {
+    let first_symbol = match codes.keys().min() {
+        Some(symbol) => *symbol,
+        None => return Ok(None),
+    };
 
-    for entry in frequencies {
-        result.push(Box::new(huffman::Node {
-            byte: Some(entry.0),
-            frequency: entry.1,
-            left: None,
-            right: None,
-        }));
+    let err_bad_codes = Error::InvalidArgument("Codes do not form a prefix code");
+
+    let mut root = Box::new(empty_node(first_symbol));
+
+    for (symbol, code) in codes {
+        let mut current_node = &mut root;
+
+        for index in (0..code.bin_length()).rev() {
+            if current_node.symbol.is_some() {
+                return Err(err_bad_codes);
+            }
+
+            current_node.lowest_symbol = std::cmp::min(current_node.lowest_symbol, *symbol);
+
+            let child = if (code.bin_repres() >> index) & 1 == 1 {
+                &mut current_node.right
+            } else {
+                &mut current_node.left
+            };
+
+            current_node = child.get_or_insert_with(|| Box::new(empty_node(*symbol)));
+        }
+
+        if current_node.symbol.is_some()
+            || current_node.left.is_some()
+            || current_node.right.is_some()
+        {
+            return Err(err_bad_codes);
+        }
+
+        current_node.symbol = Some(*symbol);
+        current_node.lowest_symbol = *symbol;
     }
 
-    Ok(result)
+    Ok(Some(root))
 }
 
-pub fn build(mut bin_heap: BinaryHeap<Box<huffman::Node>>) -> Option<Box<huffman::Node>> {
-    while bin_heap.len() > 1 {
-        let right_node = bin_heap.pop().unwrap();
-        let left_node = bin_heap.pop().unwrap();
+fn merge_items(
+    leaf_items: Vec<(u64, Vec<usize>)>,
+    packages: Vec<(u64, Vec<usize>)>,
+) -> Vec<(u64, Vec<usize>)> {
+    let mut result = Vec::with_capacity(leaf_items.len() + packages.len());
+
+    let mut leaf_items = leaf_items.into_iter().peekable();
+    let mut packages = packages.into_iter().peekable();
 
-        let joined_node = huffman::Node {
-            frequency: left_node.frequency + right_node.frequency,
-            byte: None,
-            left: Some(left_node),
-            right: Some(right_node),
+    loop {
+        let take_leaf = match (leaf_items.peek(), packages.peek()) {
+            (Some(leaf), Some(package)) => leaf.0 <= package.0,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break,
         };
 
-        bin_heap.push(Box::new(joined_node));
+        if take_leaf {
+            result.push(leaf_items.next().unwrap());
+        } else {
+            result.push(packages.next().unwrap());
+        }
     }
 
-    bin_heap.pop()
+    result
+}
+
+fn assign_frequencies<S: Symbol>(
+    node: &mut huffman::Node<S>,
+    frequencies: &HashMap<S, u64>,
+) -> u64 {
+    node.frequency = match node.symbol {
+        Some(symbol) => frequencies[&symbol],
+        None => {
+            node.left
+                .as_mut()
+                .map_or(0, |left| assign_frequencies(left, frequencies))
+                + node
+                    .right
+                    .as_mut()
+                    .map_or(0, |right| assign_frequencies(right, frequencies))
+        }
+    };
+
+    node.frequency
+}
+
+fn leaves_cost<S: Symbol>(node: &huffman::Node<S>, depth: u64) -> u64 {
+    if node.symbol.is_some() {
+        return node.frequency * depth;
+    }
+
+    node.left
+        .as_ref()
+        .map_or(0, |left| leaves_cost(left, depth + 1))
+        + node
+            .right
+            .as_ref()
+            .map_or(0, |right| leaves_cost(right, depth + 1))
+}
+
+// `lowest_symbol` is lowered while the codes below the node are added.
+fn empty_node<S: Symbol>(lowest_symbol: S) -> huffman::Node<S> {
+    huffman::Node {
+        frequency: 0,
+        symbol: None,
+        lowest_symbol,
+        left: None,
+        right: None,
+    }
+}
+
+// The first `count` Fibonacci numbers as the frequencies of bytes 0.., which give the deepest
+// tree for their total; shared by the tests of long codes.
+#[cfg(test)]
+pub(crate) fn fibonacci_frequencies(count: usize) -> [u64; 256] {
+    let mut frequencies = [0u64; 256];
+    let (mut current, mut next) = (1u64, 1u64);
+
+    for frequency in frequencies.iter_mut().take(count) {
+        *frequency = current;
+
+        let following = current + next;
+        current = next;
+        next = following;
+    }
+
+    frequencies
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::compressor;
-    use crate::huffman::Node;
+    use crate::error::Error;
+    use crate::huffman::{HuffmanCode, Node};
+    use crate::{compressor, decompressor};
     use std::cmp::Ordering;
-    use std::collections::BinaryHeap;
+    use std::collections::{BinaryHeap, HashMap};
     use std::fs::File;
-    use std::io::BufReader;
+    use std::io::{BufReader, Cursor};
     use std::option::Option::Some;
     use std::path::Path;
 
     fn create_letter_node(letter: char, frequency: u64) -> Box<Node> {
-        Box::new(Node {
-            frequency,
-            byte: Some(letter as u8),
-            left: None,
-            right: None,
-        })
+        Box::new(Node::new_leaf(letter as u8, frequency))
     }
 
     fn expected_initial_tree() -> BinaryHeap<Box<Node>> {
@@ -85,6 +292,27 @@ mod tests {
         bin_heap
     }
 
+    #[test]
+    fn test_from_frequencies() {
+        let mut frequencies = [0u64; 256];
+        frequencies[b'A' as usize] = 2;
+        frequencies[b'B' as usize] = 2;
+        frequencies[b'C' as usize] = 2;
+        frequencies[b'D' as usize] = 3;
+        frequencies[b'E' as usize] = 11;
+        frequencies[b'F' as usize] = 5;
+
+        let mut actual = compressor::tree::from_frequencies(&frequencies).into_sorted_vec();
+        let mut expected = expected_initial_tree().into_sorted_vec();
+
+        assert_eq!(expected.len(), actual.len());
+
+        while let (Some(expected_node), Some(actual_node)) = (expected.pop(), actual.pop()) {
+            assert_eq!(expected_node.symbol, actual_node.symbol);
+            assert_eq!(expected_node.frequency, actual_node.frequency);
+        }
+    }
+
     #[test]
     fn test_init() {
         let file_in = File::open(Path::new("test-data/dataset_0/expected.txt"))
@@ -95,9 +323,9 @@ mod tests {
 
         let mut sorted_by_symbol = initial_tree.into_vec();
         sorted_by_symbol.sort_by(|node_left, node_right| {
-            if node_left.byte == node_right.byte {
+            if node_left.symbol == node_right.symbol {
                 Ordering::Equal
-            } else if node_left.byte > node_right.byte {
+            } else if node_left.symbol > node_right.symbol {
                 Ordering::Greater
             } else {
                 Ordering::Less
@@ -117,23 +345,21 @@ mod tests {
         if let Some(node_expected) = expected {
             let node_actual = actual.as_ref().expect("Actual should not be None");
 
-            assert_eq!(node_expected.byte, node_actual.byte);
+            assert_eq!(node_expected.symbol, node_actual.symbol);
             assert_eq!(node_expected.frequency, node_actual.frequency);
 
             compare_trees(&node_expected.left, &node_actual.left);
             compare_trees(&node_expected.right, &node_actual.right);
         } else if let Some(_node_actual) = actual {
-            assert!(false, "Actual node should not be Some");
+            panic!("Actual node should not be Some");
         }
     }
 
     fn create_joined_node(frequency: u64, left: Box<Node>, right: Box<Node>) -> Box<Node> {
-        Box::new(Node {
-            frequency,
-            byte: None,
-            left: Some(left),
-            right: Some(right),
-        })
+        let joined_node = Node::new_joined(left, right);
+        assert_eq!(joined_node.frequency, frequency);
+
+        Box::new(joined_node)
     }
 
     #[test]
@@ -141,31 +367,209 @@ mod tests {
         let initial_tree = expected_initial_tree();
         let huffman_tree = compressor::tree::build(initial_tree);
 
-        let expected_huffman_tree = Box::new(Node {
-            frequency: 25,
-            byte: None,
-            left: Some(Box::new(Node {
-                frequency: 14,
-                byte: None,
-                right: Some(create_joined_node(
-                    5,
-                    create_letter_node('D', 3),
-                    create_letter_node('B', 2),
-                )),
-                left: Some(Box::new(Node {
-                    frequency: 9,
-                    byte: None,
-                    left: Some(create_letter_node('F', 5)),
-                    right: Some(create_joined_node(
-                        4,
-                        create_letter_node('C', 2),
-                        create_letter_node('A', 2),
-                    )),
-                })),
-            })),
-            right: Some(create_letter_node('E', 11)),
-        });
+        let expected_huffman_tree = create_joined_node(
+            25,
+            create_joined_node(
+                14,
+                create_joined_node(
+                    9,
+                    create_joined_node(5, create_letter_node('D', 3), create_letter_node('C', 2)),
+                    create_joined_node(4, create_letter_node('B', 2), create_letter_node('A', 2)),
+                ),
+                create_letter_node('F', 5),
+            ),
+            create_letter_node('E', 11),
+        );
 
         compare_trees(&Some(expected_huffman_tree), &huffman_tree);
     }
+
+    #[test]
+    fn test_build_deterministic() {
+        let mut expected_lengths = None;
+
+        for rotation in 0..20 {
+            let mut leaves = fibonacci_heap(20).into_vec();
+            leaves
+                .iter_mut()
+                .for_each(|leaf| leaf.frequency = 1 + leaf.frequency % 4);
+            leaves.rotate_left(rotation);
+
+            if rotation % 2 == 1 {
+                leaves.reverse();
+            }
+
+            let huffman_tree = compressor::tree::build(leaves.into_iter().collect()).unwrap();
+            let mut lengths: Vec<(u8, u8)> = compressor::calc_lengths(&huffman_tree)
+                .unwrap()
+                .into_iter()
+                .collect();
+            lengths.sort();
+
+            match &expected_lengths {
+                Some(expected) => assert_eq!(expected, &lengths),
+                None => expected_lengths = Some(lengths),
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_codes() {
+        let huffman_tree = compressor::tree::build(expected_initial_tree());
+        let codes = compressor::calc_codes(huffman_tree.as_ref().unwrap()).unwrap();
+
+        let rebuilt_tree = compressor::tree::from_codes(&codes).expect("Valid codes");
+
+        let mut rebuilt_codes = compressor::calc_codes(rebuilt_tree.as_ref().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|(byte, code)| (byte, code.bin_repres(), code.bin_length()))
+            .collect::<Vec<_>>();
+        rebuilt_codes.sort();
+
+        let mut expected_codes = codes
+            .into_iter()
+            .map(|(byte, code)| (byte, code.bin_repres(), code.bin_length()))
+            .collect::<Vec<_>>();
+        expected_codes.sort();
+
+        assert_eq!(expected_codes, rebuilt_codes);
+    }
+
+    fn fibonacci_heap(count: usize) -> BinaryHeap<Box<Node>> {
+        compressor::tree::from_frequencies(&compressor::tree::fibonacci_frequencies(count))
+    }
+
+    fn max_depth(node: &Node) -> u8 {
+        let left = node.left.as_ref().map_or(0, |left| max_depth(left) + 1);
+        let right = node.right.as_ref().map_or(0, |right| max_depth(right) + 1);
+
+        std::cmp::max(left, right)
+    }
+
+    #[test]
+    fn test_build_limited() {
+        let unconstrained = compressor::tree::build(fibonacci_heap(20)).unwrap();
+        assert_eq!(max_depth(&unconstrained), 19);
+
+        let limited = compressor::tree::build_limited(fibonacci_heap(20), 8)
+            .unwrap()
+            .unwrap();
+        assert_eq!(max_depth(&limited), 8);
+        assert_eq!(limited.frequency, unconstrained.frequency);
+
+        let unconstrained_cost = compressor::tree::cost(&unconstrained);
+        let limited_cost = compressor::tree::cost(&limited);
+
+        assert_eq!(unconstrained_cost, 46_344);
+        assert!(limited_cost > unconstrained_cost);
+
+        let limit_18 = compressor::tree::build_limited(fibonacci_heap(20), 18)
+            .unwrap()
+            .unwrap();
+        let limit_19 = compressor::tree::build_limited(fibonacci_heap(20), 19)
+            .unwrap()
+            .unwrap();
+
+        assert!(compressor::tree::cost(&limit_18) > unconstrained_cost);
+        assert_eq!(compressor::tree::cost(&limit_19), unconstrained_cost);
+    }
+
+    #[test]
+    fn test_build_limited_round_trip() {
+        let message: Vec<u8> = (0..20u8)
+            .flat_map(|symbol| std::iter::repeat_n(symbol, 1 + symbol as usize * symbol as usize))
+            .collect();
+
+        let initial_tree = compressor::tree::init(&mut Cursor::new(&message)).unwrap();
+        let limited = compressor::tree::build_limited(initial_tree, 5)
+            .unwrap()
+            .unwrap();
+        let codes = compressor::calc_codes(&limited).unwrap();
+
+        assert!(codes.values().all(|code| code.bin_length() <= 5));
+        assert_eq!(codes.len(), 20);
+
+        let compressed = compressor::compress(&message, &codes).unwrap();
+
+        assert_eq!(compressed.1 as u64, compressor::tree::cost(&limited));
+        assert_eq!(
+            decompressor::decompress(&compressed, &limited).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_build_limited_too_many_symbols() {
+        assert!(matches!(
+            compressor::tree::build_limited(fibonacci_heap(9), 3),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_codes_not_prefix_free() {
+        let mut short_code = HuffmanCode::new();
+        short_code.push_bit(true).unwrap();
+
+        let mut long_code = short_code;
+        long_code.push_bit(false).unwrap();
+
+        let mut codes = HashMap::new();
+        codes.insert(b'A', short_code);
+        codes.insert(b'B', long_code);
+
+        assert!(compressor::tree::from_codes(&codes).is_err());
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    enum LzSymbol {
+        Literal(u8),
+        Match { length: u16 },
+        EndOfBlock,
+    }
+
+    #[test]
+    fn test_build_enum_symbols() {
+        let mut message = Vec::new();
+
+        for index in 0..500u16 {
+            message.push(LzSymbol::Literal(b'a' + (index % 5) as u8));
+
+            if index % 3 == 0 {
+                message.push(LzSymbol::Match {
+                    length: 3 + index % 4,
+                });
+            }
+        }
+
+        message.push(LzSymbol::EndOfBlock);
+
+        let mut frequencies = HashMap::new();
+
+        for symbol in &message {
+            *frequencies.entry(*symbol).or_insert(0u64) += 1;
+        }
+
+        let huffman_tree =
+            compressor::tree::build(compressor::tree::from_symbol_frequencies(&frequencies))
+                .unwrap();
+        let codes = compressor::calc_codes(&huffman_tree).unwrap();
+
+        assert_eq!(codes.len(), 10);
+        assert!(
+            codes[&LzSymbol::EndOfBlock].bin_length()
+                > codes[&LzSymbol::Literal(b'a')].bin_length()
+        );
+
+        let compressed = compressor::compress_symbols(&message, &codes).unwrap();
+
+        assert_eq!(compressed.1 as u64, compressor::tree::cost(&huffman_tree));
+
+        let decoding_tree = compressor::tree::from_codes(&codes).unwrap().unwrap();
+        assert_eq!(
+            decompressor::decompress(&compressed, &decoding_tree).unwrap(),
+            message
+        );
+    }
 }
@@ -0,0 +1,681 @@
+use crate::compressor::context::ContextTables;
+use crate::compressor::stream::MAX_BLOCK_SIZE;
+use crate::error::{Error, Result};
+use crate::{checksum, huffman};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"HUFF";
+pub const VERSION: u8 = 6;
+
+const TAG_END: u8 = 0;
+const TAG_HUFFMAN_BLOCK: u8 = 1;
+const TAG_ADAPTIVE_BLOCK: u8 = 2;
+const TAG_STORED_BLOCK: u8 = 3;
+const TAG_CONTEXT_BLOCK: u8 = 4;
+const TAG_SHARED_BLOCK: u8 = 5;
+const TAG_LIVE_STREAM: u8 = 6;
+
+// tag, original length and both checksums of a stored block
+pub const STORED_OVERHEAD: usize = 17;
+
+// the symbols of adaptive blocks are the bytes alone
+pub const ADAPTIVE_SYMBOL_COUNT: usize = 256;
+
+// the symbols of a live stream after the 256 bytes
+pub const LIVE_END: u16 = 256;
+pub const LIVE_FLUSH: u16 = 257;
+pub const LIVE_SYMBOL_COUNT: usize = 258;
+
+pub enum BlockKind {
+    Huffman { code_lengths: HashMap<u8, u8> },
+    // coded with `vitter::VitterModel` carried over from the previous adaptive blocks
+    Adaptive,
+    // the payload is the original bytes
+    Stored,
+    // every byte is coded with the table of the byte preceding it
+    Context { tables: ContextTables },
+    // coded with the `shared_table::SharedTable` of this ID, kept outside of the container
+    Shared { table_id: u32 },
+    // coded with `vitter::VitterModel` up to its end symbol, the lengths and the checksum of the
+    // header do not apply
+    Live,
+}
+
+pub struct BlockHeader {
+    pub kind: BlockKind,
+    pub original_len: u64,
+    pub bit_len: u64,
+    // `checksum::crc32` of the original bytes
+    pub checksum: u32,
+}
+
+// Layout (integers are little-endian):
+//   magic[4] | version u8 | block * | end tag u8
+//
+// Block:
+//   block tag u8 | code lengths of all 256 symbols | original length u64 | bit length u64 |
+//   CRC-32 of the original bytes u32 | header CRC-32 u32 | payload of ceil(bit length / 8) bytes
+//
+// The header CRC-32 covers the header from its tag on, so damaged code lengths or lengths are
+// caught before anything is decoded with them.
+//
+// Adaptive blocks have no code lengths, their codes evolve with the symbols seen so far.
+// Context blocks replace the code lengths with a bitmap of 256 bits, set for the preceding bytes
+// having a table of their own, followed by the code lengths of the shared table and those of the
+// own tables in the order of their preceding bytes.
+// Shared blocks replace the code lengths with the u32 ID of the table they were coded with.
+// Stored blocks keep data that would not shrink as is:
+//   block tag u8 | original length u64 | CRC-32 u32 | header CRC-32 u32 | original bytes
+// Live streams are written as the data arrives, so nothing is known about them up front:
+//   block tag u8 | header CRC-32 u32 | symbols up to `LIVE_END`, zero padded to a byte |
+//   CRC-32 u32
+// Their symbols are bytes and `LIVE_FLUSH`, which is zero padded to a byte too so that every
+// byte written before it can be decoded right away.
+//
+// The original length of a block is at most `compressor::stream::MAX_BLOCK_SIZE`.
+// Code lengths are run-length encoded as { run length - 1 u8 | code length u8 } pairs,
+// codes themselves are canonical and restored with `compressor::codes_from_lengths`.
+pub fn write_file_header<W: Write>(writer: &mut W) -> Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION])?;
+
+    Ok(())
+}
+
+pub fn read_file_header<R: Read>(reader: &mut R) -> Result<()> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+
+    if magic != MAGIC {
+        return Err(Error::InvalidHeader("Not a compressed file"));
+    }
+
+    let version = read_u8(reader)?;
+
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    Ok(())
+}
+
+// The payload of `header.bit_len` bits is expected to follow right after.
+pub fn write_block_header<W: Write>(writer: &mut W, header: &BlockHeader) -> Result<()> {
+    let mut fields = Vec::new();
+    write_header_fields(&mut fields, header)?;
+
+    writer.write_all(&fields)?;
+    writer.write_all(&checksum::crc32(&fields).to_le_bytes())?;
+
+    Ok(())
+}
+
+fn write_header_fields<W: Write>(writer: &mut W, header: &BlockHeader) -> Result<()> {
+    match &header.kind {
+        BlockKind::Huffman { code_lengths } => {
+            writer.write_all(&[TAG_HUFFMAN_BLOCK])?;
+            write_code_lengths(writer, code_lengths)?;
+        }
+        BlockKind::Adaptive => writer.write_all(&[TAG_ADAPTIVE_BLOCK])?,
+        BlockKind::Context { tables } => {
+            writer.write_all(&[TAG_CONTEXT_BLOCK])?;
+            write_context_tables(writer, tables)?;
+        }
+        BlockKind::Shared { table_id } => {
+            writer.write_all(&[TAG_SHARED_BLOCK])?;
+            writer.write_all(&table_id.to_le_bytes())?;
+        }
+        BlockKind::Live => {
+            writer.write_all(&[TAG_LIVE_STREAM])?;
+            return Ok(());
+        }
+        BlockKind::Stored => {
+            writer.write_all(&[TAG_STORED_BLOCK])?;
+            writer.write_all(&header.original_len.to_le_bytes())?;
+            writer.write_all(&header.checksum.to_le_bytes())?;
+            return Ok(());
+        }
+    }
+
+    writer.write_all(&header.original_len.to_le_bytes())?;
+    writer.write_all(&header.bit_len.to_le_bytes())?;
+    writer.write_all(&header.checksum.to_le_bytes())?;
+
+    Ok(())
+}
+
+// Returns `None` once the end tag is reached.
+pub fn read_block_header<R: Read>(reader: &mut R) -> Result<Option<BlockHeader>> {
+    let mut recorder = Recorder {
+        reader,
+        recorded: Vec::new(),
+    };
+
+    let header = match read_header_fields(&mut recorder)? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    let actual = checksum::crc32(&recorder.recorded);
+
+    if read_u32(reader)? != actual {
+        return Err(Error::InvalidHeader(
+            "Block header does not match its checksum",
+        ));
+    }
+
+    Ok(Some(header))
+}
+
+// Keeps a copy of the bytes read through it, for the header checksum.
+struct Recorder<'a, R: Read> {
+    reader: &'a mut R,
+    recorded: Vec<u8>,
+}
+
+impl<R: Read> Read for Recorder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read_len = self.reader.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..read_len]);
+
+        Ok(read_len)
+    }
+}
+
+fn read_header_fields<R: Read>(reader: &mut R) -> Result<Option<BlockHeader>> {
+    let kind = match read_u8(reader)? {
+        TAG_END => return Ok(None),
+        TAG_HUFFMAN_BLOCK => BlockKind::Huffman {
+            code_lengths: read_code_lengths(reader)?,
+        },
+        TAG_ADAPTIVE_BLOCK => BlockKind::Adaptive,
+        TAG_CONTEXT_BLOCK => BlockKind::Context {
+            tables: read_context_tables(reader)?,
+        },
+        TAG_SHARED_BLOCK => BlockKind::Shared {
+            table_id: read_u32(reader)?,
+        },
+        TAG_STORED_BLOCK => {
+            let original_len = read_original_len(reader)?;
+            let bit_len = original_len * 8;
+
+            return Ok(Some(BlockHeader {
+                kind: BlockKind::Stored,
+                original_len,
+                bit_len,
+                checksum: read_u32(reader)?,
+            }));
+        }
+        TAG_LIVE_STREAM => {
+            return Ok(Some(BlockHeader {
+                kind: BlockKind::Live,
+                original_len: 0,
+                bit_len: 0,
+                checksum: 0,
+            }))
+        }
+        _ => return Err(Error::InvalidHeader("Unknown block type")),
+    };
+
+    let original_len = read_original_len(reader)?;
+    let bit_len = read_u64(reader)?;
+    let checksum = read_u32(reader)?;
+
+    Ok(Some(BlockHeader {
+        kind,
+        original_len,
+        bit_len,
+        checksum,
+    }))
+}
+
+pub fn read_payload<R: Read>(reader: &mut R, header: &BlockHeader) -> Result<Vec<u8>> {
+    let payload_len = header.bit_len.div_ceil(8);
+
+    let mut payload = Vec::new();
+    reader.take(payload_len).read_to_end(&mut payload)?;
+
+    if payload.len() as u64 != payload_len {
+        return Err(Error::Io(std::io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "Compressed block is truncated",
+        )));
+    }
+
+    Ok(payload)
+}
+
+pub fn write_end<W: Write>(writer: &mut W) -> Result<()> {
+    writer.write_all(&[TAG_END])?;
+
+    Ok(())
+}
+
+pub fn write_code_lengths<W: Write>(writer: &mut W, code_lengths: &HashMap<u8, u8>) -> Result<()> {
+    let all_lengths: Vec<u8> = (0..=255u8)
+        .map(|byte| *code_lengths.get(&byte).unwrap_or(&0))
+        .collect();
+
+    for run in all_lengths.chunk_by(|left, right| left == right) {
+        writer.write_all(&[(run.len() - 1) as u8, run[0]])?;
+    }
+
+    Ok(())
+}
+
+pub fn read_code_lengths<R: Read>(reader: &mut R) -> Result<HashMap<u8, u8>> {
+    let err_bad_lengths = "Code lengths do not form a prefix code";
+
+    let mut code_lengths = HashMap::<u8, u8>::new();
+    let mut next_symbol = 0usize;
+    let mut kraft_sum = 0u128;
+
+    while next_symbol < 256 {
+        let run_len = read_u8(reader)? as usize + 1;
+        let length = read_u8(reader)?;
+
+        if next_symbol + run_len > 256 || length > huffman::HuffmanCode::MAX_LENGTH {
+            return Err(Error::InvalidHeader(err_bad_lengths));
+        }
+
+        if length > 0 {
+            for symbol in next_symbol..next_symbol + run_len {
+                code_lengths.insert(symbol as u8, length);
+            }
+
+            kraft_sum += (run_len as u128) << (huffman::HuffmanCode::MAX_LENGTH - length);
+        }
+
+        next_symbol += run_len;
+    }
+
+    if kraft_sum > 1u128 << huffman::HuffmanCode::MAX_LENGTH {
+        return Err(Error::InvalidHeader(err_bad_lengths));
+    }
+
+    Ok(code_lengths)
+}
+
+fn write_context_tables<W: Write>(writer: &mut W, tables: &ContextTables) -> Result<()> {
+    let mut bitmap = [0u8; 32];
+
+    for (context, table) in tables.by_context.iter().enumerate() {
+        if table.is_some() {
+            bitmap[context / 8] |= 0x80 >> (context % 8);
+        }
+    }
+
+    writer.write_all(&bitmap)?;
+    write_code_lengths(writer, &tables.shared)?;
+
+    for lengths in tables.by_context.iter().flatten() {
+        write_code_lengths(writer, lengths)?;
+    }
+
+    Ok(())
+}
+
+fn read_context_tables<R: Read>(reader: &mut R) -> Result<ContextTables> {
+    let mut bitmap = [0u8; 32];
+    reader.read_exact(&mut bitmap)?;
+
+    let shared = read_code_lengths(reader)?;
+    let mut by_context = Vec::with_capacity(256);
+
+    for context in 0..256 {
+        if bitmap[context / 8] & (0x80 >> (context % 8)) != 0 {
+            by_context.push(Some(read_code_lengths(reader)?));
+        } else {
+            by_context.push(None);
+        }
+    }
+
+    Ok(ContextTables { shared, by_context })
+}
+
+// Blocks are never longer than the encoder makes them, so decoding one takes bounded memory
+// whatever the header claims.
+fn read_original_len<R: Read>(reader: &mut R) -> Result<u64> {
+    let original_len = read_u64(reader)?;
+
+    if original_len > MAX_BLOCK_SIZE as u64 {
+        return Err(Error::InvalidHeader(
+            "Block is longer than the maximum block size",
+        ));
+    }
+
+    Ok(original_len)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+
+    Ok(buf[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compressor::context::ContextTables;
+    use crate::compressor::stream::MAX_BLOCK_SIZE;
+    use crate::error::Error;
+    use crate::{checksum, container};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_write() {
+        let mut code_lengths = HashMap::new();
+        code_lengths.insert(b'A', 1);
+        code_lengths.insert(b'B', 2);
+        code_lengths.insert(b'C', 2);
+
+        let header = container::BlockHeader {
+            kind: container::BlockKind::Huffman { code_lengths },
+            original_len: 3,
+            bit_len: 5,
+            checksum: 0x1234_5678,
+        };
+
+        let mut written = Vec::new();
+        container::write_file_header(&mut written).unwrap();
+        container::write_block_header(&mut written, &header).unwrap();
+        written.push(0b01011000);
+        container::write_end(&mut written).unwrap();
+
+        let mut expected = b"HUFF".to_vec();
+        expected.push(6);
+        expected.push(1);
+        expected.extend_from_slice(&[b'A' - 1, 0, 0, 1, 1, 2, 255 - b'C' - 1, 0]);
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(&5u64.to_le_bytes());
+        expected.extend_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        let header_checksum = checksum::crc32(&expected[5..]);
+        expected.extend_from_slice(&header_checksum.to_le_bytes());
+        expected.push(0b01011000);
+        expected.push(0);
+
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_read() {
+        let mut code_lengths = HashMap::new();
+        code_lengths.insert(0u8, 2);
+        code_lengths.insert(b'B', 1);
+        code_lengths.insert(255u8, 2);
+
+        let header = container::BlockHeader {
+            kind: container::BlockKind::Huffman {
+                code_lengths: code_lengths.clone(),
+            },
+            original_len: 4,
+            bit_len: 6,
+            checksum: 0xdead_beef,
+        };
+
+        let mut written = Vec::new();
+        container::write_file_header(&mut written).unwrap();
+        container::write_block_header(&mut written, &header).unwrap();
+        written.push(0b01100100);
+        container::write_end(&mut written).unwrap();
+
+        let mut reader = written.as_slice();
+        container::read_file_header(&mut reader).unwrap();
+
+        let read_header = container::read_block_header(&mut reader)
+            .unwrap()
+            .expect("A block is expected");
+
+        match &read_header.kind {
+            container::BlockKind::Huffman {
+                code_lengths: read_lengths,
+            } => assert_eq!(read_lengths, &code_lengths),
+            _ => panic!("A Huffman block is expected"),
+        }
+        assert_eq!(read_header.original_len, 4);
+        assert_eq!(read_header.bit_len, 6);
+        assert_eq!(read_header.checksum, 0xdead_beef);
+
+        let payload = container::read_payload(&mut reader, &read_header).unwrap();
+        assert_eq!(payload, vec![0b01100100]);
+
+        assert!(container::read_block_header(&mut reader).unwrap().is_none());
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_read_adaptive() {
+        let header = container::BlockHeader {
+            kind: container::BlockKind::Adaptive,
+            original_len: 2,
+            bit_len: 9,
+            checksum: 7,
+        };
+
+        let mut written = Vec::new();
+        container::write_block_header(&mut written, &header).unwrap();
+
+        let mut expected = vec![2u8];
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(&7u32.to_le_bytes());
+        expected.extend_from_slice(&checksum::crc32(&expected).to_le_bytes());
+        assert_eq!(written, expected);
+
+        let read_header = container::read_block_header(&mut written.as_slice())
+            .unwrap()
+            .unwrap();
+
+        assert!(matches!(read_header.kind, container::BlockKind::Adaptive));
+        assert_eq!(read_header.original_len, 2);
+        assert_eq!(read_header.bit_len, 9);
+    }
+
+    #[test]
+    fn test_read_context() {
+        let mut by_context = vec![None; 256];
+        by_context[b'q' as usize] = Some(HashMap::from([(b'u', 1)]));
+        by_context[255] = Some(HashMap::from([(0, 1), (255, 1)]));
+
+        let tables = ContextTables {
+            shared: HashMap::from([(b'q', 1), (b'u', 2), (255, 2)]),
+            by_context,
+        };
+
+        let header = container::BlockHeader {
+            kind: container::BlockKind::Context {
+                tables: tables.clone(),
+            },
+            original_len: 2,
+            bit_len: 2,
+            checksum: 3,
+        };
+
+        let mut written = Vec::new();
+        container::write_block_header(&mut written, &header).unwrap();
+
+        // the tag, the bitmap, three tables, lengths and checksums
+        assert_eq!(written.len(), 1 + 32 + 12 + 6 + 6 + 24);
+        assert_eq!(written[1 + b'q' as usize / 8], 0x80 >> (b'q' % 8));
+        assert_eq!(written[32], 1);
+
+        let read_header = container::read_block_header(&mut written.as_slice())
+            .unwrap()
+            .unwrap();
+
+        match &read_header.kind {
+            container::BlockKind::Context {
+                tables: read_tables,
+            } => assert_eq!(read_tables, &tables),
+            _ => panic!("A context block is expected"),
+        }
+    }
+
+    #[test]
+    fn test_read_shared() {
+        let header = container::BlockHeader {
+            kind: container::BlockKind::Shared {
+                table_id: 0xcafe_f00d,
+            },
+            original_len: 2,
+            bit_len: 5,
+            checksum: 3,
+        };
+
+        let mut written = Vec::new();
+        container::write_block_header(&mut written, &header).unwrap();
+
+        let mut expected = vec![5u8, 0x0d, 0xf0, 0xfe, 0xca];
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&5u64.to_le_bytes());
+        expected.extend_from_slice(&3u32.to_le_bytes());
+        expected.extend_from_slice(&checksum::crc32(&expected).to_le_bytes());
+        assert_eq!(written, expected);
+
+        let read_header = container::read_block_header(&mut written.as_slice())
+            .unwrap()
+            .unwrap();
+
+        assert!(matches!(
+            read_header.kind,
+            container::BlockKind::Shared {
+                table_id: 0xcafe_f00d
+            }
+        ));
+        assert_eq!(read_header.bit_len, 5);
+    }
+
+    #[test]
+    fn test_read_stored() {
+        let header = container::BlockHeader {
+            kind: container::BlockKind::Stored,
+            original_len: 3,
+            bit_len: 24,
+            checksum: crate::checksum::crc32(b"raw"),
+        };
+
+        let mut written = Vec::new();
+        container::write_block_header(&mut written, &header).unwrap();
+        assert_eq!(written.len(), container::STORED_OVERHEAD);
+        written.extend_from_slice(b"raw");
+
+        let mut reader = written.as_slice();
+        let read_header = container::read_block_header(&mut reader).unwrap().unwrap();
+
+        assert!(matches!(read_header.kind, container::BlockKind::Stored));
+        assert_eq!(read_header.bit_len, 24);
+        assert_eq!(read_header.checksum, crate::checksum::crc32(b"raw"));
+        assert_eq!(
+            container::read_payload(&mut reader, &read_header).unwrap(),
+            b"raw".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_read_bad_magic() {
+        let err = container::read_file_header(&mut &b"GZIP\x04"[..])
+            .err()
+            .unwrap();
+
+        assert!(matches!(err, Error::InvalidHeader(_)));
+
+        let err = container::read_file_header(&mut &b"HUFF\x07"[..])
+            .err()
+            .unwrap();
+
+        assert!(matches!(err, Error::UnsupportedVersion(7)));
+    }
+
+    #[test]
+    fn test_read_truncated_payload() {
+        let header = container::BlockHeader {
+            kind: container::BlockKind::Adaptive,
+            original_len: 0,
+            bit_len: 17,
+            checksum: 0,
+        };
+
+        let err = container::read_payload(&mut &[0u8, 0][..], &header)
+            .err()
+            .unwrap();
+
+        match err {
+            Error::Io(io_err) => assert_eq!(io_err.kind(), std::io::ErrorKind::UnexpectedEof),
+            _ => panic!("An I/O error is expected"),
+        }
+    }
+
+    #[test]
+    fn test_read_oversized_block() {
+        for kind in [container::BlockKind::Adaptive, container::BlockKind::Stored] {
+            let header = container::BlockHeader {
+                kind,
+                original_len: MAX_BLOCK_SIZE as u64 + 1,
+                bit_len: 64,
+                checksum: 0,
+            };
+
+            let mut written = Vec::new();
+            container::write_block_header(&mut written, &header).unwrap();
+
+            assert!(matches!(
+                container::read_block_header(&mut written.as_slice()),
+                Err(Error::InvalidHeader(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_read_corrupted_header() {
+        let header = container::BlockHeader {
+            kind: container::BlockKind::Huffman {
+                code_lengths: HashMap::from([(b'a', 1), (b'b', 1)]),
+            },
+            original_len: 2,
+            bit_len: 2,
+            checksum: 0,
+        };
+
+        let mut written = Vec::new();
+        container::write_block_header(&mut written, &header).unwrap();
+
+        // lost codes for "a" and "b", a longer bit length and a damaged header checksum
+        for (byte, bit) in [(4, 0x01), (19, 0x08), (29, 0x40)] {
+            let mut corrupted = written.clone();
+            corrupted[byte] ^= bit;
+
+            assert!(matches!(
+                container::read_block_header(&mut corrupted.as_slice()),
+                Err(Error::InvalidHeader(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_read_oversubscribed_lengths() {
+        let mut written = vec![1u8];
+        written.extend_from_slice(&[2, 1, 252, 0]);
+        written.extend_from_slice(&[0u8; 16]);
+
+        let err = container::read_block_header(&mut written.as_slice())
+            .err()
+            .unwrap();
+
+        assert!(matches!(err, Error::InvalidHeader(_)));
+    }
+}
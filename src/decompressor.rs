@@ -1,40 +1,102 @@
-use crate::{bin_operations, huffman};
+use crate::bit_reader::BitReader;
+use crate::error::{Error, Result};
+use crate::huffman::{self, Symbol};
+use std::io::Write;
 
-pub fn decompress(
+pub mod live;
+pub mod stream;
+pub mod table;
+
+const OUTPUT_CHUNK_SIZE: usize = 8192;
+
+pub fn decompress<S: Symbol>(
+    compressed_stream: &(Vec<u8>, usize),
+    huffman_tree_root: &huffman::Node<S>,
+) -> Result<Vec<S>> {
+    let mut result = Vec::<S>::new();
+
+    walk(compressed_stream, huffman_tree_root, |chunk| {
+        result.extend_from_slice(chunk);
+        Ok(())
+    })?;
+
+    Ok(result)
+}
+
+pub fn decompress_into<W: Write>(
+    compressed_stream: &(Vec<u8>, usize),
+    huffman_tree_root: &huffman::Node,
+    writer: &mut W,
+) -> Result<u64> {
+    let mut total_written = 0u64;
+
+    walk(compressed_stream, huffman_tree_root, |chunk| {
+        writer.write_all(chunk)?;
+        total_written += chunk.len() as u64;
+        Ok(())
+    })?;
+
+    Ok(total_written)
+}
+
+// Passes the decoded symbols to `emit` in chunks of up to `OUTPUT_CHUNK_SIZE`.
+fn walk<S: Symbol, F: FnMut(&[S]) -> Result<()>>(
     compressed_stream: &(Vec<u8>, usize),
-    huffman_tree_root: &Box<huffman::Node>,
-) -> String {
-    let compr_data = &compressed_stream.0;
+    huffman_tree_root: &huffman::Node<S>,
+    mut emit: F,
+) -> Result<()> {
+    let mut reader = BitReader::new(compressed_stream.0.as_slice());
     let compr_data_bin_len = compressed_stream.1;
 
-    let mut result = String::new();
+    let mut chunk = Vec::<S>::with_capacity(OUTPUT_CHUNK_SIZE);
+
     let mut current_node = huffman_tree_root;
+    let mut symbol_start = 0usize;
 
-    for index in 0..compr_data_bin_len {
-        if bin_operations::check_bit_set(compr_data, index).expect("Wrong bit num") {
-            if let Some(right_node) = &current_node.right {
-                current_node = right_node;
-            }
+    for bit_num in 0..compr_data_bin_len {
+        let child = if reader.read_bit()? {
+            &current_node.right
         } else {
-            if let Some(left_node) = &current_node.left {
-                current_node = left_node;
+            &current_node.left
+        };
+
+        current_node = match child {
+            Some(child_node) => child_node,
+            None => {
+                return Err(Error::CorruptPayload {
+                    offset: symbol_start as u64,
+                })
             }
-        }
+        };
 
-        if let Some(byte) = &current_node.byte {
+        if let Some(symbol) = current_node.symbol {
             current_node = huffman_tree_root;
-            result.push(*byte as char);
+            symbol_start = bit_num + 1;
+            chunk.push(symbol);
+
+            if chunk.len() == OUTPUT_CHUNK_SIZE {
+                emit(&chunk)?;
+                chunk.clear();
+            }
         }
     }
 
-    result
+    // the stream ended in the middle of a code
+    if symbol_start != compr_data_bin_len {
+        return Err(Error::CorruptPayload {
+            offset: symbol_start as u64,
+        });
+    }
+
+    emit(&chunk)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::decompressor::decompress;
+    use crate::decompressor::{decompress, decompress_into};
+    use crate::error::Error;
     use crate::{compressor, huffman};
-    use std::collections::BinaryHeap;
+    use std::collections::{BinaryHeap, HashMap};
     use std::fs::File;
     use std::io::Read;
     use std::path::Path;
@@ -42,47 +104,17 @@ mod tests {
     fn initial_tree() -> BinaryHeap<Box<huffman::Node>> {
         let mut bottom_leaves = BinaryHeap::<Box<huffman::Node>>::new();
 
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 2,
-            byte: Some('A' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 2,
-            byte: Some('B' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 2,
-            byte: Some('C' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 3,
-            byte: Some('D' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 5,
-            byte: Some('F' as u8),
-            left: None,
-            right: None,
-        }));
-
-        bottom_leaves.push(Box::new(huffman::Node {
-            frequency: 10,
-            byte: Some('E' as u8),
-            left: None,
-            right: None,
-        }));
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'A', 2)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'B', 2)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'C', 2)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'D', 3)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'F', 5)));
+
+        bottom_leaves.push(Box::new(huffman::Node::new_leaf(b'E', 10)));
 
         bottom_leaves
     }
@@ -93,39 +125,83 @@ mod tests {
 
         let expected_message = match file_in {
             Ok(mut file) => {
-                let mut str = String::new();
-                file.read_to_string(&mut str).unwrap();
-                str
-            }
-            Err(_) => {
-                assert!(false, "Could not open a test dataset");
-                String::new()
+                let mut bytes = Vec::new();
+                file.read_to_end(&mut bytes).unwrap();
+                bytes
             }
+            Err(_) => panic!("Could not open a test dataset"),
         };
 
         let compressed_message = (
             vec![
-                0b00100010u8,
+                0b11101110u8,
+                0b10101010,
+                0b10110011,
+                0b00110100,
                 0b00000000,
-                0b00000000,
-                0b01100110,
+                0b11011111,
                 0b11111111,
-                0b11110110,
-                0b10010010,
-                0b10000000,
+                0b00000000,
             ],
             57usize,
         );
 
         let initial_tree = initial_tree();
-        let huffman_tree = compressor::tree::build(initial_tree);
+        let huffman_tree = compressor::tree::build(initial_tree).expect("No empty tree possible.");
+        let codes = compressor::calc_codes(&huffman_tree).unwrap();
+        let decoding_tree = compressor::tree::from_codes(&codes).unwrap();
 
         assert_eq!(
-            decompress(
-                &compressed_message,
-                &huffman_tree.expect("No empty tree possible.")
-            ),
+            decompress(&compressed_message, &decoding_tree.unwrap()).unwrap(),
             expected_message
         );
     }
+
+    #[test]
+    fn test_decompress_binary() {
+        let mut bottom_leaves = BinaryHeap::<Box<huffman::Node>>::new();
+
+        for (byte, frequency) in &[(0x00u8, 1u64), (0x80, 2), (0xc3, 3), (0xff, 4)] {
+            bottom_leaves.push(Box::new(huffman::Node::new_leaf(*byte, *frequency)));
+        }
+
+        let huffman_tree = compressor::tree::build(bottom_leaves).expect("No empty tree possible.");
+        let codes = compressor::calc_codes(&huffman_tree).unwrap();
+
+        let message = vec![0xffu8, 0x00, 0xc3, 0x80, 0xff, 0xc3, 0x80, 0xff];
+        let compressed_message = compressor::compress(&message, &codes).unwrap();
+
+        let huffman_tree = compressor::tree::from_codes(&codes).unwrap().unwrap();
+
+        assert_eq!(
+            decompress(&compressed_message, &huffman_tree).unwrap(),
+            message
+        );
+
+        let mut written = Vec::new();
+        let written_len =
+            decompress_into(&compressed_message, &huffman_tree, &mut written).unwrap();
+
+        assert_eq!(written_len, message.len() as u64);
+        assert_eq!(written, message);
+    }
+
+    #[test]
+    fn test_decompress_corrupted() {
+        // "A" is 0, "B" is 10 and no code starts with 11
+        let codes = compressor::codes_from_lengths(&HashMap::from([(b'A', 1), (b'B', 2)])).unwrap();
+        let huffman_tree = compressor::tree::from_codes(&codes).unwrap().unwrap();
+
+        let invalid_path = (vec![0b01011000u8], 5);
+        assert!(matches!(
+            decompress(&invalid_path, &huffman_tree),
+            Err(Error::CorruptPayload { offset: 3 })
+        ));
+
+        let ends_mid_symbol = (vec![0b01010000u8], 4);
+        assert!(matches!(
+            decompress(&ends_mid_symbol, &huffman_tree),
+            Err(Error::CorruptPayload { offset: 3 })
+        ));
+    }
 }
@@ -0,0 +1,228 @@
+use crate::checksum::Crc32;
+use crate::container;
+use crate::error::{Error, Result};
+use crate::vitter::VitterModel;
+use std::io::Read;
+
+// bytes decoded at most before they are handed out
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// A live stream being decoded, see `compressor::live::LiveEncoder`. The input is read a byte at
+// a time, so decoding stops right at a flush instead of waiting for more input, and nothing past
+// the stream is consumed; the reader should be buffered.
+pub struct LiveStream {
+    model: Box<VitterModel>,
+    bits: BitSource,
+    checksum: Crc32,
+}
+
+struct BitSource {
+    byte: u8,
+    bits_left: u8,
+    bits_consumed: u64,
+}
+
+impl BitSource {
+    fn next_bit<R: Read>(&mut self, reader: &mut R) -> std::io::Result<bool> {
+        if self.bits_left == 0 {
+            let mut buf = [0u8];
+            reader.read_exact(&mut buf)?;
+
+            self.byte = buf[0];
+            self.bits_left = 8;
+        }
+
+        self.bits_left -= 1;
+        self.bits_consumed += 1;
+
+        Ok(self.byte >> self.bits_left & 1 == 1)
+    }
+
+    fn align(&mut self) {
+        self.bits_consumed += self.bits_left as u64;
+        self.bits_left = 0;
+    }
+}
+
+impl LiveStream {
+    pub fn new() -> LiveStream {
+        LiveStream {
+            model: Box::new(VitterModel::new(container::LIVE_SYMBOL_COUNT)),
+            bits: BitSource {
+                byte: 0,
+                bits_left: 0,
+                bits_consumed: 0,
+            },
+            checksum: Crc32::new(),
+        }
+    }
+
+    // Appends the bytes up to the next flush, the end of the stream or `CHUNK_SIZE` bytes to
+    // `block`. Returns true once the end is reached and the checksum following it is read, and
+    // verified unless `verify` is false; the bytes handed out before are not verified yet.
+    pub fn decode_chunk<R: Read>(
+        &mut self,
+        reader: &mut R,
+        block: &mut Vec<u8>,
+        verify: bool,
+    ) -> Result<bool> {
+        let LiveStream {
+            model,
+            bits,
+            checksum,
+        } = self;
+
+        let start = block.len();
+        let mut ended = false;
+
+        while block.len() - start < CHUNK_SIZE {
+            let symbol = model.decode(bits.bits_consumed, || bits.next_bit(reader))?;
+
+            match symbol {
+                container::LIVE_FLUSH => {
+                    bits.align();
+                    break;
+                }
+                container::LIVE_END => {
+                    bits.align();
+                    ended = true;
+                    break;
+                }
+                byte => block.push(byte as u8),
+            }
+        }
+
+        checksum.update(&block[start..]);
+
+        if !ended {
+            return Ok(false);
+        }
+
+        let mut expected = [0u8; 4];
+        reader.read_exact(&mut expected)?;
+
+        let expected = u32::from_le_bytes(expected);
+        let actual = checksum.finish();
+
+        if verify && actual != expected {
+            return Err(Error::ChecksumMismatch { expected, actual });
+        }
+
+        Ok(true)
+    }
+
+    // `checksum::crc32` of the bytes decoded so far.
+    pub fn checksum(&self) -> u32 {
+        self.checksum.finish()
+    }
+}
+
+impl Default for LiveStream {
+    fn default() -> LiveStream {
+        LiveStream::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compressor::live::LiveEncoder;
+    use crate::decompressor::live::LiveStream;
+    use crate::error::Error;
+    use std::io::{ErrorKind, Read, Write};
+
+    fn live_payload(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut encoder = LiveEncoder::new(Vec::new()).unwrap();
+
+        for chunk in chunks {
+            encoder.write_all(chunk).unwrap();
+            encoder.flush().unwrap();
+        }
+
+        // without the file header, the block header and the end tag
+        let compressed = encoder.finish().unwrap();
+        compressed[10..compressed.len() - 1].to_vec()
+    }
+
+    #[test]
+    fn test_decode_chunks() {
+        let payload = live_payload(&[b"first line\n", b"", b"second line\n"]);
+
+        let mut stream = LiveStream::new();
+        let mut reader = payload.as_slice();
+        let mut block = Vec::new();
+
+        assert!(!stream.decode_chunk(&mut reader, &mut block, true).unwrap());
+        assert_eq!(block, b"first line\n");
+
+        assert!(!stream.decode_chunk(&mut reader, &mut block, true).unwrap());
+        assert_eq!(block, b"first line\n");
+
+        assert!(!stream.decode_chunk(&mut reader, &mut block, true).unwrap());
+        assert!(stream.decode_chunk(&mut reader, &mut block, true).unwrap());
+        assert_eq!(block, b"first line\nsecond line\n");
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_decode_stops_at_flush() {
+        let payload = live_payload(&[b"sent", b"not sent yet"]);
+
+        // a reader ending right after the first flush, as a socket with nothing more to read
+        let flushed_len = {
+            let mut encoder = LiveEncoder::new(Vec::new()).unwrap();
+            encoder.write_all(b"sent").unwrap();
+            encoder.flush().unwrap();
+            encoder.get_ref().len() - 10
+        };
+
+        let mut block = Vec::new();
+        LiveStream::new()
+            .decode_chunk(&mut &payload[..flushed_len], &mut block, true)
+            .unwrap();
+
+        assert_eq!(block, b"sent");
+    }
+
+    #[test]
+    fn test_decode_checksum_mismatch() {
+        let mut payload = live_payload(&[b"checked"]);
+        let last = payload.len() - 1;
+        payload[last] ^= 1;
+
+        let mut stream = LiveStream::new();
+        let mut reader = payload.as_slice();
+        let mut block = Vec::new();
+
+        stream.decode_chunk(&mut reader, &mut block, true).unwrap();
+
+        assert!(matches!(
+            stream.decode_chunk(&mut reader, &mut block, true),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        // "a" as new, then again as new after the NYT code "0"
+        let payload = [0b0011_0000, 0b1000_1100, 0b0010_0000];
+
+        assert!(matches!(
+            LiveStream::new().decode_chunk(&mut &payload[..], &mut Vec::new(), true),
+            Err(Error::CorruptPayload { offset: 19 })
+        ));
+
+        // the errors of the reader are not taken for corrupt data
+        struct Failing;
+
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(ErrorKind::InvalidData.into())
+            }
+        }
+
+        assert!(matches!(
+            LiveStream::new().decode_chunk(&mut Failing, &mut Vec::new(), true),
+            Err(Error::Io(err)) if err.kind() == ErrorKind::InvalidData
+        ));
+    }
+}
@@ -0,0 +1,536 @@
+use crate::bit_reader::BitReader;
+use crate::compressor::context::{self, ContextTables};
+use crate::decompressor::live::LiveStream;
+use crate::decompressor::table;
+use crate::error::{Error, Result};
+use crate::shared_table::SharedTable;
+use crate::vitter::VitterModel;
+use crate::{checksum, compressor, container};
+use std::io::Read;
+
+// Decodes a stream written by `HuffmanEncoder` one block at a time, or by `LiveEncoder` one
+// flush at a time.
+pub struct HuffmanDecoder<R: Read> {
+    reader: R,
+    block: Vec<u8>,
+    block_pos: usize,
+    adaptive_model: Option<Box<VitterModel>>,
+    // the ID of the shared table and its decode table
+    shared_table: Option<(u32, table::DecodeTable)>,
+    // set from the tag of a live stream to its end
+    live_stream: Option<Box<LiveStream>>,
+    verify: bool,
+    header_read: bool,
+    finished: bool,
+}
+
+impl<R: Read> HuffmanDecoder<R> {
+    pub fn new(reader: R) -> HuffmanDecoder<R> {
+        HuffmanDecoder {
+            reader,
+            block: Vec::new(),
+            block_pos: 0,
+            adaptive_model: None,
+            shared_table: None,
+            live_stream: None,
+            verify: true,
+            header_read: false,
+            finished: false,
+        }
+    }
+
+    // Decodes streams whose blocks refer to a pre-trained table, as written by
+    // `HuffmanEncoder::with_shared_table`.
+    pub fn with_shared_table(reader: R, table: &SharedTable) -> HuffmanDecoder<R> {
+        let mut decoder = HuffmanDecoder::new(reader);
+        decoder.shared_table = Some((table.id, table.code_book.decode_table()));
+
+        decoder
+    }
+
+    // Checksums of the decoded blocks are verified unless turned off, which gets the data out of
+    // a damaged stream as far as it still decodes.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Returns false once the end of the stream is reached.
+    fn next_block(&mut self) -> Result<bool> {
+        if !self.header_read {
+            container::read_file_header(&mut self.reader)?;
+            self.header_read = true;
+        }
+
+        self.block.clear();
+        self.block_pos = 0;
+
+        if let Some(stream) = &mut self.live_stream {
+            if stream.decode_chunk(&mut self.reader, &mut self.block, self.verify)? {
+                self.live_stream = None;
+            }
+
+            return Ok(true);
+        }
+
+        let header = match container::read_block_header(&mut self.reader)? {
+            Some(header) => header,
+            None => return Ok(false),
+        };
+
+        let payload = container::read_payload(&mut self.reader, &header)?;
+
+        match &header.kind {
+            container::BlockKind::Huffman { .. }
+            | container::BlockKind::Stored
+            | container::BlockKind::Context { .. } => {
+                decode_block(&header, payload, &mut self.block)?;
+            }
+            container::BlockKind::Adaptive => {
+                let model = self.adaptive_model.get_or_insert_with(|| {
+                    Box::new(VitterModel::new(container::ADAPTIVE_SYMBOL_COUNT))
+                });
+
+                decode_adaptive(&payload, &header, model, &mut self.block)?;
+            }
+            container::BlockKind::Shared { table_id } => match &self.shared_table {
+                Some((id, decode_table)) if id == table_id => {
+                    decode_huffman(&header, payload, decode_table, &mut self.block)?;
+                }
+                _ => return Err(Error::MissingTable(*table_id)),
+            },
+            container::BlockKind::Live => {
+                self.live_stream = Some(Box::default());
+                return Ok(true);
+            }
+        }
+
+        if self.verify {
+            let actual = checksum::crc32(&self.block);
+
+            if actual != header.checksum {
+                return Err(Error::ChecksumMismatch {
+                    expected: header.checksum,
+                    actual,
+                });
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+// Decodes a block read with `container::read_block_header` and `container::read_payload`
+// without the rest of the stream, its checksum is left to the caller. Only blocks carrying their
+// own code table can be decoded this way, adaptive ones depend on all the preceding blocks,
+// shared ones on a table kept elsewhere and live streams have no block boundaries at all.
+pub fn decode_block(
+    header: &container::BlockHeader,
+    payload: Vec<u8>,
+    block: &mut Vec<u8>,
+) -> Result<()> {
+    let code_lengths = match &header.kind {
+        container::BlockKind::Huffman { code_lengths } => code_lengths,
+        container::BlockKind::Stored => {
+            block.extend_from_slice(&payload);
+            return Ok(());
+        }
+        container::BlockKind::Context { tables } => {
+            return decode_context(&payload, header, tables, block)
+        }
+        container::BlockKind::Adaptive => {
+            return Err(Error::InvalidArgument(
+                "Adaptive blocks cannot be decoded on their own",
+            ))
+        }
+        container::BlockKind::Shared { table_id } => return Err(Error::MissingTable(*table_id)),
+        container::BlockKind::Live => {
+            return Err(Error::InvalidArgument(
+                "Live streams cannot be decoded as a block",
+            ))
+        }
+    };
+
+    let codes = compressor::codes_from_lengths(code_lengths)?;
+
+    decode_huffman(header, payload, &table::DecodeTable::new(&codes), block)
+}
+
+fn decode_huffman(
+    header: &container::BlockHeader,
+    payload: Vec<u8>,
+    decode_table: &table::DecodeTable,
+    block: &mut Vec<u8>,
+) -> Result<()> {
+    let written_len =
+        table::decompress_into(&(payload, header.bit_len as usize), decode_table, block)?;
+
+    if written_len != header.original_len {
+        return Err(Error::CorruptPayload {
+            offset: header.bit_len,
+        });
+    }
+
+    Ok(())
+}
+
+fn decode_context(
+    payload: &[u8],
+    header: &container::BlockHeader,
+    tables: &ContextTables,
+    block: &mut Vec<u8>,
+) -> Result<()> {
+    let shared_table = table::DecodeTable::new(&compressor::codes_from_lengths(&tables.shared)?);
+
+    let own_tables = tables
+        .by_context
+        .iter()
+        .map(|lengths| match lengths {
+            Some(lengths) => Ok(Some(table::DecodeTable::new(
+                &compressor::codes_from_lengths(lengths)?,
+            ))),
+            None => Ok(None),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut reader = BitReader::new(payload);
+    let mut context = context::INITIAL_CONTEXT;
+
+    for _ in 0..header.original_len {
+        let decode_table = own_tables[context as usize]
+            .as_ref()
+            .unwrap_or(&shared_table);
+
+        context = table::decode_symbol(&mut reader, decode_table, header.bit_len)?;
+        block.push(context);
+    }
+
+    if reader.bits_consumed() != header.bit_len {
+        return Err(Error::CorruptPayload {
+            offset: reader.bits_consumed(),
+        });
+    }
+
+    Ok(())
+}
+
+fn decode_adaptive(
+    payload: &[u8],
+    header: &container::BlockHeader,
+    model: &mut VitterModel,
+    block: &mut Vec<u8>,
+) -> Result<()> {
+    let mut reader = BitReader::new(payload);
+
+    for _ in 0..header.original_len {
+        match model.decode(reader.bits_consumed(), || reader.read_bit()) {
+            Ok(byte) => block.push(byte as u8),
+            Err(err @ Error::CorruptPayload { .. }) => return Err(err),
+            // the payload is in memory, it only runs out when corrupt
+            Err(_) => {
+                return Err(Error::CorruptPayload {
+                    offset: reader.bits_consumed(),
+                })
+            }
+        }
+    }
+
+    if reader.bits_consumed() != header.bit_len {
+        return Err(Error::CorruptPayload {
+            offset: reader.bits_consumed(),
+        });
+    }
+
+    Ok(())
+}
+
+impl<R: Read> Read for HuffmanDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.block_pos == self.block.len() {
+            if self.finished || !self.next_block()? {
+                self.finished = true;
+                return Ok(0);
+            }
+        }
+
+        let taken = std::cmp::min(buf.len(), self.block.len() - self.block_pos);
+        buf[..taken].copy_from_slice(&self.block[self.block_pos..self.block_pos + taken]);
+        self.block_pos += taken;
+
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compressor::live::LiveEncoder;
+    use crate::compressor::stream::HuffmanEncoder;
+    use crate::container;
+    use crate::decompressor::stream::{self, HuffmanDecoder};
+    use crate::error::Error;
+    use crate::shared_table::{self, SharedTable};
+    use std::io::{Cursor, ErrorKind, Read, Write};
+
+    fn compress(message: &[u8], block_size: usize) -> Vec<u8> {
+        let mut encoder = HuffmanEncoder::with_any_block_size(Vec::new(), block_size).unwrap();
+        encoder.write_all(message).unwrap();
+
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_read() {
+        let message: Vec<u8> = (0..5000u32)
+            .map(|index| (index % 7 * index % 13) as u8)
+            .collect();
+
+        let compressed = compress(&message, 777);
+
+        let mut decoder = HuffmanDecoder::new(compressed.as_slice());
+        let mut decompressed = Vec::new();
+        let mut buf = [0u8; 100];
+
+        loop {
+            let read_len = decoder.read(&mut buf).unwrap();
+
+            if read_len == 0 {
+                break;
+            }
+
+            decompressed.extend_from_slice(&buf[..read_len]);
+        }
+
+        assert_eq!(decompressed, message);
+        assert_eq!(decoder.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_read_followed_by_data() {
+        let mut compressed = compress(b"payload", 4);
+        compressed.extend_from_slice(b"trailer");
+
+        let mut decoder = HuffmanDecoder::new(compressed.as_slice());
+        let mut decompressed = Vec::new();
+        decoder.read_to_end(&mut decompressed).unwrap();
+
+        assert_eq!(decompressed, b"payload".to_vec());
+        assert_eq!(decoder.into_inner(), b"trailer");
+    }
+
+    #[test]
+    fn test_decode_single_block() {
+        let message: Vec<u8> = (0..3000u32).map(|index| (index / 100) as u8).collect();
+        let compressed = compress(&message, 1000);
+
+        let mut reader = compressed.as_slice();
+        container::read_file_header(&mut reader).unwrap();
+
+        let mut blocks = Vec::new();
+
+        while let Some(header) = container::read_block_header(&mut reader).unwrap() {
+            let payload = container::read_payload(&mut reader, &header).unwrap();
+            blocks.push((header, payload));
+        }
+
+        assert_eq!(blocks.len(), 3);
+
+        for (index, (header, payload)) in blocks.into_iter().enumerate().rev() {
+            let mut block = Vec::new();
+            stream::decode_block(&header, payload, &mut block).unwrap();
+
+            assert_eq!(block, &message[index * 1000..(index + 1) * 1000]);
+        }
+    }
+
+    #[test]
+    fn test_read_adaptive() {
+        let message: Vec<u8> = (0..5000u32)
+            .map(|index| (index % 7 * index % 13) as u8)
+            .collect();
+
+        let mut encoder = HuffmanEncoder::adaptive(Vec::new());
+
+        for chunk in message.chunks(600) {
+            encoder.write_all(chunk).unwrap();
+            encoder.flush().unwrap();
+        }
+
+        let compressed = encoder.finish().unwrap();
+
+        let mut decompressed = Vec::new();
+        HuffmanDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+
+        assert_eq!(decompressed, message);
+    }
+
+    #[test]
+    fn test_read_live() {
+        let mut encoder = LiveEncoder::new(Vec::new()).unwrap();
+        encoder.write_all(b"first line\n").unwrap();
+        encoder.flush().unwrap();
+
+        // everything flushed decodes before the rest of the stream is written
+        let mut decoder = HuffmanDecoder::new(encoder.get_ref().as_slice());
+        let mut buf = [0u8; 100];
+        let read_len = decoder.read(&mut buf).unwrap();
+
+        assert_eq!(&buf[..read_len], b"first line\n");
+
+        encoder.write_all(b"second line\n").unwrap();
+        let mut compressed = encoder.finish().unwrap();
+
+        let mut decompressed = Vec::new();
+        HuffmanDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+
+        assert_eq!(decompressed, b"first line\nsecond line\n");
+
+        // the checksum follows the end symbol
+        let checksum_pos = compressed.len() - 2;
+        compressed[checksum_pos] ^= 1;
+
+        let err = HuffmanDecoder::new(compressed.as_slice())
+            .read_to_end(&mut Vec::new())
+            .err()
+            .unwrap();
+
+        assert!(matches!(Error::from(err), Error::ChecksumMismatch { .. }));
+    }
+
+    #[test]
+    fn test_read_shared_table() {
+        let mut frequencies = [0u64; 256];
+        let corpus = b"the quick brown fox jumps over the lazy dog. ".repeat(100);
+        shared_table::count_frequencies(&mut Cursor::new(corpus), &mut frequencies).unwrap();
+
+        let table = SharedTable::from_frequencies(&frequencies).unwrap();
+
+        let message =
+            b"the lazy fox jumps over the quick dog, the quick dog jumps over the lazy fox";
+        let mut encoder = HuffmanEncoder::with_shared_table(Vec::new(), &table);
+        encoder.write_all(message).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // header, shared block header and end tag around less than 5 bits a byte
+        assert_eq!(compressed[5], 5);
+        assert!(compressed.len() < 5 + 29 + message.len() * 5 / 8 + 1);
+
+        let mut decompressed = Vec::new();
+        HuffmanDecoder::with_shared_table(compressed.as_slice(), &table)
+            .read_to_end(&mut decompressed)
+            .unwrap();
+
+        assert_eq!(decompressed, message);
+
+        let other_table = SharedTable::from_frequencies(&[1u64; 256]).unwrap();
+
+        for mut decoder in [
+            HuffmanDecoder::new(compressed.as_slice()),
+            HuffmanDecoder::with_shared_table(compressed.as_slice(), &other_table),
+        ] {
+            let err = decoder.read_to_end(&mut Vec::new()).err().unwrap();
+
+            assert!(matches!(
+                Error::from(err),
+                Error::MissingTable(table_id) if table_id == table.id
+            ));
+        }
+    }
+
+    #[test]
+    fn test_read_checksum_mismatch() {
+        let message: Vec<u8> = (0..=255u8).collect();
+        let mut compressed = compress(&message, 1000);
+
+        // the block is stored, so flipping a bit of the payload keeps it decodable
+        compressed[5 + container::STORED_OVERHEAD + 10] ^= 1;
+
+        let err = HuffmanDecoder::new(compressed.as_slice())
+            .read_to_end(&mut Vec::new())
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            err.into_inner().unwrap().downcast_ref::<Error>(),
+            Some(Error::ChecksumMismatch { .. })
+        ));
+
+        let mut decoder = HuffmanDecoder::new(compressed.as_slice());
+        decoder.set_verify(false);
+
+        let mut decompressed = Vec::new();
+        decoder.read_to_end(&mut decompressed).unwrap();
+
+        assert_eq!(decompressed.len(), message.len());
+        assert_eq!(decompressed[10], message[10] ^ 1);
+    }
+
+    #[test]
+    fn test_read_corrupted_payload() {
+        let compressed = compress(&[b'a'; 100], 1000);
+
+        // tag, run-length coded lengths of "a" alone, lengths and both checksums
+        let payload_start = 5 + 1 + 6 + 8 + 8 + 4 + 4;
+        assert_eq!(compressed.len(), payload_start + 13 + 1);
+
+        for (byte, bit, offset) in [(2, 0x80, 16), (12, 0x10, 99)] {
+            let mut corrupted = compressed.clone();
+            corrupted[payload_start + byte] |= bit;
+
+            let err = HuffmanDecoder::new(corrupted.as_slice())
+                .read_to_end(&mut Vec::new())
+                .err()
+                .unwrap();
+
+            assert!(matches!(
+                Error::from(err),
+                Error::CorruptPayload { offset: found } if found == offset
+            ));
+        }
+    }
+
+    #[test]
+    fn test_read_corrupted_adaptive() {
+        // "a" as new, then again as new after the NYT code "0"
+        let mut compressed = Vec::new();
+        container::write_file_header(&mut compressed).unwrap();
+        container::write_block_header(
+            &mut compressed,
+            &container::BlockHeader {
+                kind: container::BlockKind::Adaptive,
+                original_len: 2,
+                bit_len: 17,
+                checksum: crate::checksum::crc32(b"aa"),
+            },
+        )
+        .unwrap();
+        compressed.extend_from_slice(&[0b0110_0001, 0b0011_0000, 0b1000_0000]);
+        container::write_end(&mut compressed).unwrap();
+
+        let err = HuffmanDecoder::new(compressed.as_slice())
+            .read_to_end(&mut Vec::new())
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            Error::from(err),
+            Error::CorruptPayload { offset: 17 }
+        ));
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let compressed = compress(b"some message to be truncated", 8);
+
+        let mut decoder = HuffmanDecoder::new(&compressed[..compressed.len() - 1]);
+        let err = decoder.read_to_end(&mut Vec::new()).err().unwrap();
+
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::bit_reader::BitReader;
+use crate::error::{Error, Result};
+use crate::huffman::{self, Symbol};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+pub const PRIMARY_BITS: u8 = 10;
+pub const SECONDARY_BITS: u8 = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Entry<S> {
+    Invalid,
+    Symbol {
+        symbol: S,
+        length: u8,
+    },
+    // two short codes fitting into a single primary lookup
+    Pair {
+        first: S,
+        second: S,
+        first_length: u8,
+        length: u8,
+    },
+    // the code continues in a nested table indexed by the next `bits` bits
+    Link {
+        base: usize,
+        bits: u8,
+    },
+}
+
+pub struct DecodeTable<S = u8> {
+    entries: Vec<Entry<S>>,
+}
+
+impl<S: Symbol> DecodeTable<S> {
+    pub fn new(codes: &HashMap<S, huffman::HuffmanCode>) -> DecodeTable<S> {
+        let mut entries = Vec::<Entry<S>>::new();
+
+        let suffixes: Vec<(u64, u8, S)> = codes
+            .iter()
+            .map(|(symbol, code)| (code.bin_repres(), code.bin_length(), *symbol))
+            .collect();
+
+        fill_level(&mut entries, &suffixes, PRIMARY_BITS);
+        pair_short_codes(&mut entries);
+
+        DecodeTable { entries }
+    }
+}
+
+pub fn decompress<S: Symbol>(
+    compressed_stream: &(Vec<u8>, usize),
+    table: &DecodeTable<S>,
+) -> Result<Vec<S>> {
+    let mut result = Vec::<S>::new();
+
+    decode(compressed_stream, table, |symbol| {
+        result.push(symbol);
+        Ok(())
+    })?;
+
+    Ok(result)
+}
+
+pub fn decompress_into<W: Write>(
+    compressed_stream: &(Vec<u8>, usize),
+    table: &DecodeTable,
+    writer: &mut W,
+) -> Result<u64> {
+    let mut chunk = Vec::<u8>::with_capacity(super::OUTPUT_CHUNK_SIZE);
+    let mut total_written = 0u64;
+
+    decode(compressed_stream, table, |byte| {
+        chunk.push(byte);
+
+        if chunk.len() == super::OUTPUT_CHUNK_SIZE {
+            writer.write_all(&chunk)?;
+            total_written += chunk.len() as u64;
+            chunk.clear();
+        }
+
+        Ok(())
+    })?;
+
+    writer.write_all(&chunk)?;
+    total_written += chunk.len() as u64;
+
+    Ok(total_written)
+}
+
+// Passes every decoded symbol to `emit`.
+fn decode<S: Symbol, F: FnMut(S) -> Result<()>>(
+    compressed_stream: &(Vec<u8>, usize),
+    table: &DecodeTable<S>,
+    mut emit: F,
+) -> Result<()> {
+    let mut reader = BitReader::new(compressed_stream.0.as_slice());
+    let compr_data_bin_len = compressed_stream.1;
+
+    let mut bit_num = 0usize;
+    let mut symbol_start = 0usize;
+    let mut table_base = 0usize;
+    let mut table_bits = PRIMARY_BITS;
+
+    let err_corrupt = |symbol_start: usize| Error::CorruptPayload {
+        offset: symbol_start as u64,
+    };
+
+    while bit_num < compr_data_bin_len {
+        let index = reader.peek_bits(table_bits)? as usize;
+
+        let consumed = match table.entries[table_base + index] {
+            Entry::Symbol { symbol, length } => {
+                if bit_num + length as usize > compr_data_bin_len {
+                    return Err(err_corrupt(symbol_start));
+                }
+
+                emit(symbol)?;
+                length
+            }
+            Entry::Pair {
+                first,
+                second,
+                first_length,
+                length,
+            } => {
+                if bit_num + first_length as usize > compr_data_bin_len {
+                    return Err(err_corrupt(symbol_start));
+                }
+
+                emit(first)?;
+
+                if bit_num + length as usize <= compr_data_bin_len {
+                    emit(second)?;
+                    length
+                } else {
+                    first_length
+                }
+            }
+            Entry::Link { base, bits } => {
+                // a linked code is longer than the bits left
+                if bit_num + table_bits as usize >= compr_data_bin_len {
+                    return Err(err_corrupt(symbol_start));
+                }
+
+                reader.consume(table_bits)?;
+                bit_num += table_bits as usize;
+                table_base = base;
+                table_bits = bits;
+                continue;
+            }
+            Entry::Invalid => return Err(err_corrupt(symbol_start)),
+        };
+
+        reader.consume(consumed)?;
+        bit_num += consumed as usize;
+        symbol_start = bit_num;
+
+        table_base = 0;
+        table_bits = PRIMARY_BITS;
+    }
+
+    Ok(())
+}
+
+// Decodes a single symbol for callers switching tables between symbols; `bit_len` is the length
+// of the whole payload, which `reader` reads from its start.
+pub fn decode_symbol<S: Symbol, R: Read>(
+    reader: &mut BitReader<R>,
+    table: &DecodeTable<S>,
+    bit_len: u64,
+) -> Result<S> {
+    let err_corrupt = Error::CorruptPayload {
+        offset: reader.bits_consumed(),
+    };
+
+    let mut table_base = 0usize;
+    let mut table_bits = PRIMARY_BITS;
+
+    loop {
+        let index = reader.peek_bits(table_bits)? as usize;
+
+        let (symbol, length) = match table.entries[table_base + index] {
+            Entry::Symbol { symbol, length } => (symbol, length),
+            // the second symbol may need another table
+            Entry::Pair {
+                first,
+                first_length,
+                ..
+            } => (first, first_length),
+            Entry::Link { base, bits } => {
+                if reader.bits_consumed() + table_bits as u64 >= bit_len {
+                    return Err(err_corrupt);
+                }
+
+                reader.consume(table_bits)?;
+                table_base = base;
+                table_bits = bits;
+                continue;
+            }
+            Entry::Invalid => return Err(err_corrupt),
+        };
+
+        if reader.bits_consumed() + length as u64 > bit_len {
+            return Err(err_corrupt);
+        }
+
+        reader.consume(length)?;
+
+        return Ok(symbol);
+    }
+}
+
+// Codes are given as (remaining bits, remaining length, symbol) relative to the level being
+// filled.
+fn fill_level<S: Symbol>(entries: &mut Vec<Entry<S>>, codes: &[(u64, u8, S)], bits: u8) -> usize {
+    let base = entries.len();
+    entries.resize(base + (1 << bits), Entry::Invalid);
+
+    let mut long_codes = HashMap::<usize, Vec<(u64, u8, S)>>::new();
+
+    for (code, length, symbol) in codes {
+        if *length <= bits {
+            let first = (*code as usize) << (bits - length);
+
+            for index in first..first + (1 << (bits - length)) {
+                entries[base + index] = Entry::Symbol {
+                    symbol: *symbol,
+                    length: *length,
+                };
+            }
+        } else {
+            let rest_length = length - bits;
+            let prefix = (code >> rest_length) as usize;
+            let rest = code & ((1u64 << rest_length) - 1);
+
+            long_codes
+                .entry(prefix)
+                .or_default()
+                .push((rest, rest_length, *symbol));
+        }
+    }
+
+    let mut prefixes: Vec<usize> = long_codes.keys().copied().collect();
+    prefixes.sort();
+
+    for prefix in prefixes {
+        let group = &long_codes[&prefix];
+        let longest = group.iter().map(|(_, length, _)| *length).max().unwrap();
+        let nested_bits = std::cmp::min(longest, SECONDARY_BITS);
+
+        let nested_base = fill_level(entries, group, nested_bits);
+
+        entries[base + prefix] = Entry::Link {
+            base: nested_base,
+            bits: nested_bits,
+        };
+    }
+
+    base
+}
+
+fn pair_short_codes<S: Symbol>(entries: &mut [Entry<S>]) {
+    let mask = (1usize << PRIMARY_BITS) - 1;
+
+    for index in 0..=mask {
+        if let Entry::Symbol { symbol, length } = entries[index] {
+            // the bits following the first code, zero padded on the right
+            let following = (index << length) & mask;
+
+            if let Entry::Symbol {
+                symbol: second_symbol,
+                length: second_length,
+            } = entries[following]
+            {
+                if length + second_length <= PRIMARY_BITS {
+                    entries[index] = Entry::Pair {
+                        first: symbol,
+                        second: second_symbol,
+                        first_length: length,
+                        length: length + second_length,
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compressor;
+    use crate::decompressor::{self, table};
+    use crate::error::Error;
+    use std::collections::HashMap;
+
+    fn chain_lengths(symbol_count: u8) -> HashMap<u8, u8> {
+        let mut lengths: HashMap<u8, u8> = (0..symbol_count)
+            .map(|symbol| (symbol, symbol_count - symbol))
+            .collect();
+        lengths.insert(0, symbol_count - 1);
+
+        lengths
+    }
+
+    #[test]
+    fn test_decompress_matches_tree() {
+        let message: Vec<u8> = (0..4000u32)
+            .map(|index| ((index * 7919) % 97) as u8 % ((index % 23) as u8 + 1))
+            .collect();
+
+        let initial_tree = compressor::tree::init(&mut std::io::Cursor::new(&message)).unwrap();
+        let huffman_tree = compressor::tree::build(initial_tree).unwrap();
+        let codes = compressor::calc_codes(&huffman_tree).unwrap();
+
+        let compressed = compressor::compress(&message, &codes).unwrap();
+        let decoding_tree = compressor::tree::from_codes(&codes).unwrap().unwrap();
+
+        let table = table::DecodeTable::new(&codes);
+
+        assert_eq!(table::decompress(&compressed, &table).unwrap(), message);
+        assert_eq!(
+            decompressor::decompress(&compressed, &decoding_tree).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_decompress_long_codes() {
+        let codes = compressor::codes_from_lengths(&chain_lengths(40)).unwrap();
+        assert_eq!(codes.values().map(|code| code.bin_length()).max(), Some(39));
+
+        let message: Vec<u8> = (0..40u8).chain((0..40u8).rev()).collect();
+        let compressed = compressor::compress(&message, &codes).unwrap();
+
+        let table = table::DecodeTable::new(&codes);
+
+        assert_eq!(table::decompress(&compressed, &table).unwrap(), message);
+    }
+
+    #[test]
+    fn test_decompress_pairs_at_the_end() {
+        let mut lengths = HashMap::new();
+        lengths.insert(b'A', 1);
+        lengths.insert(b'B', 2);
+        lengths.insert(b'C', 2);
+
+        let codes = compressor::codes_from_lengths(&lengths).unwrap();
+        let table = table::DecodeTable::new(&codes);
+
+        for message in &[
+            b"A".to_vec(),
+            b"AB".to_vec(),
+            b"CAAB".to_vec(),
+            b"BCA".to_vec(),
+        ] {
+            let compressed = compressor::compress(message, &codes).unwrap();
+
+            assert_eq!(&table::decompress(&compressed, &table).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_decompress_corrupted() {
+        let codes = compressor::codes_from_lengths(&HashMap::from([(b'A', 1), (b'B', 2)])).unwrap();
+        let table = table::DecodeTable::new(&codes);
+
+        let invalid_path = (vec![0b01011000u8], 5);
+        assert!(matches!(
+            table::decompress(&invalid_path, &table),
+            Err(Error::CorruptPayload { offset: 3 })
+        ));
+
+        let ends_mid_symbol = (vec![0b01010000u8], 4);
+        assert!(matches!(
+            table::decompress(&ends_mid_symbol, &table),
+            Err(Error::CorruptPayload { offset: 3 })
+        ));
+
+        // the link into the nested table is all that is left
+        let codes = compressor::codes_from_lengths(&chain_lengths(40)).unwrap();
+        let table = table::DecodeTable::new(&codes);
+        let long_prefix = (vec![0xffu8, 0xc0], 10);
+
+        assert!(matches!(
+            table::decompress(&long_prefix, &table),
+            Err(Error::CorruptPayload { offset: 0 })
+        ));
+    }
+}
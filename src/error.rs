@@ -0,0 +1,132 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    // the container or one of its block headers is malformed
+    InvalidHeader(&'static str),
+    UnsupportedVersion(u8),
+    // `offset` is the bit position within the block payload where decoding went wrong
+    CorruptPayload { offset: u64 },
+    ChecksumMismatch { expected: u32, actual: u32 },
+    CodeTooLong,
+    // the `Debug` form of a symbol without a code, see `Error::symbol_not_in_table`
+    SymbolNotInTable(String),
+    InvalidArgument(&'static str),
+    // the data was coded with a shared table of this ID, which was not given to the decoder
+    MissingTable(u32),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn symbol_not_in_table<S: fmt::Debug>(symbol: &S) -> Error {
+        Error::SymbolNotInTable(format!("{:?}", symbol))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::InvalidHeader(reason) => write!(f, "Invalid header: {}", reason),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "Unsupported format version {}", version)
+            }
+            Error::CorruptPayload { offset } => {
+                write!(f, "Compressed data is corrupt at bit {}", offset)
+            }
+            Error::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch: expected {:08x}, got {:08x}",
+                expected, actual
+            ),
+            Error::CodeTooLong => write!(
+                f,
+                "Code is longer than {} bits",
+                crate::huffman::HuffmanCode::MAX_LENGTH
+            ),
+            Error::SymbolNotInTable(symbol) => {
+                write!(f, "Symbol {} is not in the code table", symbol)
+            }
+            Error::InvalidArgument(reason) => write!(f, "{}", reason),
+            Error::MissingTable(table_id) => {
+                write!(f, "Compressed data needs the shared table {:08x}", table_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        // crate errors passed through a `Read`/`Write` implementation are unwrapped
+        if !err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return Error::Io(err);
+        }
+
+        let inner = err.into_inner().expect("Checked above.");
+
+        *inner.downcast::<Error>().expect("Checked above.")
+    }
+}
+
+// Lets `Read`/`Write` implementations report crate errors, which stay reachable via `get_ref`.
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> std::io::Error {
+        match err {
+            Error::Io(io_err) => io_err,
+            Error::InvalidArgument(_) | Error::SymbolNotInTable(_) => {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, err)
+            }
+            _ => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_io_round_trip() {
+        let io_err = std::io::Error::new(ErrorKind::UnexpectedEof, "ended");
+        let err: Error = io_err.into();
+        assert!(matches!(err, Error::Io(_)));
+
+        let io_err: std::io::Error = err.into();
+        assert_eq!(io_err.kind(), ErrorKind::UnexpectedEof);
+        assert!(io_err.get_ref().unwrap().downcast_ref::<Error>().is_none());
+    }
+
+    #[test]
+    fn test_into_io_error() {
+        let io_err: std::io::Error = Error::CorruptPayload { offset: 12 }.into();
+
+        assert_eq!(io_err.kind(), ErrorKind::InvalidData);
+        assert_eq!(io_err.to_string(), "Compressed data is corrupt at bit 12");
+        assert!(matches!(
+            io_err.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::CorruptPayload { offset: 12 })
+        ));
+
+        let err: Error = io_err.into();
+        assert!(matches!(err, Error::CorruptPayload { offset: 12 }));
+
+        let io_err: std::io::Error = Error::InvalidArgument("Bad block size").into();
+        assert_eq!(io_err.kind(), ErrorKind::InvalidInput);
+
+        let io_err: std::io::Error = Error::symbol_not_in_table(&'z').into();
+        assert_eq!(io_err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(io_err.to_string(), "Symbol 'z' is not in the code table");
+    }
+}
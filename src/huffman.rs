@@ -1,20 +1,54 @@
+use crate::error::{Error, Result};
 use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::hash::Hash;
 
-#[derive(Eq, Debug)]
-pub struct Node {
+// Anything that can be Huffman coded: bytes, wider indices such as token IDs or LZ77
+// length/distance symbols, or enum values. The order breaks ties between equal frequencies.
+pub trait Symbol: Copy + Ord + Hash + Debug {}
+
+impl<T: Copy + Ord + Hash + Debug> Symbol for T {}
+
+#[derive(Debug)]
+pub struct Node<S = u8> {
     pub frequency: u64,
-    pub byte: Option<u8>,
-    pub left: Option<Box<Node>>,
-    pub right: Option<Box<Node>>,
+    pub symbol: Option<S>,
+    pub lowest_symbol: S,
+    pub left: Option<Box<Node<S>>>,
+    pub right: Option<Box<Node<S>>>,
+}
+
+impl<S: Symbol> Node<S> {
+    pub fn new_leaf(symbol: S, frequency: u64) -> Node<S> {
+        Node {
+            frequency,
+            symbol: Some(symbol),
+            lowest_symbol: symbol,
+            left: None,
+            right: None,
+        }
+    }
+
+    pub fn new_joined(left: Box<Node<S>>, right: Box<Node<S>>) -> Node<S> {
+        Node {
+            frequency: left.frequency + right.frequency,
+            symbol: None,
+            lowest_symbol: std::cmp::min(left.lowest_symbol, right.lowest_symbol),
+            left: Some(left),
+            right: Some(right),
+        }
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct HuffmanCode {
-    bin_repres: u8,
+    bin_repres: u64,
     bin_length: u8,
 }
 
 impl HuffmanCode {
+    pub const MAX_LENGTH: u8 = 64;
+
     pub fn new() -> HuffmanCode {
         HuffmanCode {
             bin_repres: 0,
@@ -22,30 +56,54 @@ impl HuffmanCode {
         }
     }
 
-    pub fn push_bit(&mut self, set: bool) {
-        assert!(self.bin_length < 8, "Attempted to push more than 8 bits.");
+    pub fn from_parts(bin_repres: u64, bin_length: u8) -> Result<HuffmanCode> {
+        if bin_length > HuffmanCode::MAX_LENGTH {
+            return Err(Error::CodeTooLong);
+        }
+
+        if bin_length < HuffmanCode::MAX_LENGTH && bin_repres >> bin_length != 0 {
+            return Err(Error::InvalidArgument(
+                "Code representation does not fit its length",
+            ));
+        }
+
+        Ok(HuffmanCode {
+            bin_repres,
+            bin_length,
+        })
+    }
+
+    pub fn push_bit(&mut self, set: bool) -> Result<()> {
+        if self.bin_length == HuffmanCode::MAX_LENGTH {
+            return Err(Error::CodeTooLong);
+        }
 
         self.bin_repres <<= 1;
 
         if set {
-            self.bin_repres |= 0b00000001;
+            self.bin_repres |= 1;
         }
 
         self.bin_length += 1;
+
+        Ok(())
     }
 
-    pub fn pop_bit(&mut self) -> bool {
-        assert!(self.bin_length > 0, "Attempted to extract -1 bit.");
+    // Returns `None` once the code is empty.
+    pub fn pop_bit(&mut self) -> Option<bool> {
+        if self.bin_length == 0 {
+            return None;
+        }
 
-        let popped = (self.bin_repres & 0b00000001) == 1;
+        let popped = (self.bin_repres & 1) == 1;
 
         self.bin_repres >>= 1;
         self.bin_length -= 1;
 
-        popped
+        Some(popped)
     }
 
-    pub fn bin_repres(&self) -> u8 {
+    pub fn bin_repres(&self) -> u64 {
         self.bin_repres
     }
 
@@ -55,96 +113,170 @@ impl HuffmanCode {
 }
 
 //note: reversed order
-impl std::cmp::Ord for Node {
+//ties are broken by the lowest symbol in a subtree, which is unique among disjoint subtrees
+impl<S: Symbol> std::cmp::Ord for Node<S> {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.frequency.cmp(&self.frequency)
+        other
+            .frequency
+            .cmp(&self.frequency)
+            .then(other.lowest_symbol.cmp(&self.lowest_symbol))
     }
 }
 
-impl std::cmp::PartialOrd for Node {
+impl<S: Symbol> std::cmp::PartialOrd for Node<S> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl std::cmp::PartialEq for Node {
+impl<S: Symbol> std::cmp::PartialEq for Node<S> {
     fn eq(&self, other: &Self) -> bool {
-        self.frequency == other.frequency
+        self.frequency == other.frequency && self.lowest_symbol == other.lowest_symbol
     }
 }
 
+impl<S: Symbol> std::cmp::Eq for Node<S> {}
+
 #[cfg(test)]
 mod tests {
-    use crate::huffman::HuffmanCode;
+    use crate::error::Error;
+    use crate::huffman::{HuffmanCode, Node};
+    use std::collections::BinaryHeap;
+
+    #[test]
+    fn test_node_order() {
+        let mut bin_heap = BinaryHeap::new();
+
+        bin_heap.push(Box::new(Node::new_leaf(b'C', 2)));
+        bin_heap.push(Box::new(Node::new_leaf(b'B', 1)));
+        bin_heap.push(Box::new(Node::new_joined(
+            Box::new(Node::new_leaf(b'D', 1)),
+            Box::new(Node::new_leaf(b'A', 1)),
+        )));
+        bin_heap.push(Box::new(Node::new_leaf(b'E', 1)));
+
+        let popped: Vec<(u64, u8)> = std::iter::from_fn(|| bin_heap.pop())
+            .map(|node| (node.frequency, node.lowest_symbol))
+            .collect();
+
+        assert_eq!(popped, vec![(1, b'B'), (1, b'E'), (2, b'A'), (2, b'C')]);
+    }
 
     #[test]
     fn test_huff_code_operations() {
         let mut huff_code = HuffmanCode::new();
 
-        huff_code.push_bit(true);
+        huff_code.push_bit(true).unwrap();
         assert_eq!(huff_code.bin_length(), 1);
         assert_eq!(huff_code.bin_repres(), 0b00000001);
 
-        huff_code.push_bit(false);
+        huff_code.push_bit(false).unwrap();
         assert_eq!(huff_code.bin_length(), 2);
         assert_eq!(huff_code.bin_repres(), 0b00000010);
 
-        huff_code.push_bit(true);
+        huff_code.push_bit(true).unwrap();
         assert_eq!(huff_code.bin_length(), 3);
         assert_eq!(huff_code.bin_repres(), 0b00000101);
 
-        huff_code.push_bit(true);
+        huff_code.push_bit(true).unwrap();
         assert_eq!(huff_code.bin_length(), 4);
         assert_eq!(huff_code.bin_repres(), 0b00001011);
 
-        huff_code.push_bit(false);
+        huff_code.push_bit(false).unwrap();
         assert_eq!(huff_code.bin_length(), 5);
         assert_eq!(huff_code.bin_repres(), 0b00010110);
 
-        huff_code.push_bit(true);
+        huff_code.push_bit(true).unwrap();
         assert_eq!(huff_code.bin_length(), 6);
         assert_eq!(huff_code.bin_repres(), 0b00101101);
 
-        huff_code.push_bit(true);
+        huff_code.push_bit(true).unwrap();
         assert_eq!(huff_code.bin_length(), 7);
         assert_eq!(huff_code.bin_repres(), 0b01011011);
 
-        huff_code.push_bit(false);
+        huff_code.push_bit(false).unwrap();
         assert_eq!(huff_code.bin_length(), 8);
         assert_eq!(huff_code.bin_repres(), 0b10110110);
 
         //now, in reverse order
 
-        assert_eq!(huff_code.pop_bit(), false);
+        assert_eq!(huff_code.pop_bit(), Some(false));
         assert_eq!(huff_code.bin_length(), 7);
         assert_eq!(huff_code.bin_repres(), 0b01011011);
 
-        assert_eq!(huff_code.pop_bit(), true);
+        assert_eq!(huff_code.pop_bit(), Some(true));
         assert_eq!(huff_code.bin_length(), 6);
         assert_eq!(huff_code.bin_repres(), 0b00101101);
 
-        assert_eq!(huff_code.pop_bit(), true);
+        assert_eq!(huff_code.pop_bit(), Some(true));
         assert_eq!(huff_code.bin_length(), 5);
         assert_eq!(huff_code.bin_repres(), 0b00010110);
 
-        assert_eq!(huff_code.pop_bit(), false);
+        assert_eq!(huff_code.pop_bit(), Some(false));
         assert_eq!(huff_code.bin_length(), 4);
         assert_eq!(huff_code.bin_repres(), 0b00001011);
 
-        assert_eq!(huff_code.pop_bit(), true);
+        assert_eq!(huff_code.pop_bit(), Some(true));
         assert_eq!(huff_code.bin_length(), 3);
         assert_eq!(huff_code.bin_repres(), 0b00000101);
 
-        assert_eq!(huff_code.pop_bit(), true);
+        assert_eq!(huff_code.pop_bit(), Some(true));
         assert_eq!(huff_code.bin_length(), 2);
         assert_eq!(huff_code.bin_repres(), 0b00000010);
 
-        assert_eq!(huff_code.pop_bit(), false);
+        assert_eq!(huff_code.pop_bit(), Some(false));
         assert_eq!(huff_code.bin_length(), 1);
         assert_eq!(huff_code.bin_repres(), 0b00000001);
 
-        assert_eq!(huff_code.pop_bit(), true);
+        assert_eq!(huff_code.pop_bit(), Some(true));
         assert_eq!(huff_code.bin_length(), 0);
         assert_eq!(huff_code.bin_repres(), 0b00000000);
     }
+
+    #[test]
+    fn test_huff_code_long() {
+        let mut huff_code = HuffmanCode::new();
+
+        for index in 0..HuffmanCode::MAX_LENGTH {
+            huff_code.push_bit(index % 3 == 0).unwrap();
+        }
+
+        assert_eq!(huff_code.bin_length(), 64);
+        assert_eq!(huff_code.bin_repres(), 0x9249_2492_4924_9249);
+
+        for index in (0..HuffmanCode::MAX_LENGTH).rev() {
+            assert_eq!(huff_code.pop_bit(), Some(index % 3 == 0));
+        }
+
+        assert_eq!(huff_code.bin_length(), 0);
+        assert_eq!(huff_code.pop_bit(), None);
+    }
+
+    #[test]
+    fn test_huff_code_from_parts() {
+        let huff_code = HuffmanCode::from_parts(0b0110, 4).unwrap();
+
+        assert_eq!(huff_code.bin_length(), 4);
+        assert_eq!(huff_code.bin_repres(), 0b0110);
+
+        assert!(matches!(
+            HuffmanCode::from_parts(0b10110, 4),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            HuffmanCode::from_parts(0, 65),
+            Err(Error::CodeTooLong)
+        ));
+    }
+
+    #[test]
+    fn test_huff_code_too_long() {
+        let mut huff_code = HuffmanCode::new();
+
+        for _ in 0..HuffmanCode::MAX_LENGTH {
+            huff_code.push_bit(true).unwrap();
+        }
+
+        assert!(matches!(huff_code.push_bit(true), Err(Error::CodeTooLong)));
+    }
 }
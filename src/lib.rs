@@ -0,0 +1,426 @@
+//! Huffman coding of byte streams.
+//!
+//! `encode`/`decode` work on in-memory buffers, `compress`/`decompress` copy between streams
+//! through `HuffmanEncoder`/`HuffmanDecoder`; the modules expose the tree, code table and
+//! container building blocks. `compress_two_pass`, `compress_adaptive` and `compress_context`
+//! trade memory and speed for ratio, their output is read by the same `decompress`.
+//! `CodeBook` codes any `Symbol` type with a table built once from frequencies, counts or code
+//! lengths, and reused across messages. `SharedTable` does the same for byte streams too small
+//! to carry a table of their own, see `compress_with_table`. `compress_live` codes data as it
+//! arrives, for pipes and sockets.
+
+use std::io::{Cursor, ErrorKind, Read, Seek, Write};
+
+pub mod bit_reader;
+pub mod bit_writer;
+pub mod checksum;
+pub mod codebook;
+pub mod compressor;
+pub mod container;
+pub mod decompressor;
+pub mod error;
+pub mod huffman;
+pub mod shared_table;
+pub mod spool;
+pub mod stream_helpers;
+pub mod vitter;
+
+pub use codebook::CodeBook;
+pub use compressor::live::LiveEncoder;
+pub use compressor::stream::HuffmanEncoder;
+pub use decompressor::stream::HuffmanDecoder;
+pub use error::{Error, Result};
+pub use huffman::{HuffmanCode, Node, Symbol};
+pub use shared_table::SharedTable;
+
+/// Compresses `message` into a self-describing container.
+pub fn encode(message: &[u8]) -> Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+
+    compress(&mut Cursor::new(message), &mut compressed)?;
+
+    Ok(compressed)
+}
+
+/// Restores the original message from a container produced by `encode`.
+pub fn decode(compressed: &[u8]) -> Result<Vec<u8>> {
+    let mut message = Vec::new();
+
+    decompress(&mut Cursor::new(compressed), &mut message)?;
+
+    Ok(message)
+}
+
+/// Compresses everything `reader` holds into `writer`.
+pub fn compress<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+    let mut encoder = HuffmanEncoder::new(writer);
+
+    std::io::copy(reader, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// Compresses with blocks of `block_size` bytes, each carrying its own code table. The size
+/// must lie within `MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE` of `compressor::stream`.
+pub fn compress_with_block_size<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    block_size: usize,
+) -> Result<()> {
+    let mut encoder = HuffmanEncoder::with_block_size(writer, block_size)?;
+
+    std::io::copy(reader, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// Compresses `reader` with a single code table built from the whole input. The input has to
+/// be read twice, so it is spooled to memory or a temporary file first.
+pub fn compress_two_pass<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+    let mut spool = spool::Spool::new(reader)?;
+
+    let mut frequencies = [0u64; 256];
+    compressor::histogram::count_reader(&mut spool, &mut frequencies)?;
+
+    let code_lengths = compressor::lengths_from_frequencies(&frequencies)?;
+
+    spool.rewind()?;
+
+    let mut encoder = HuffmanEncoder::with_code_lengths(writer, code_lengths)?;
+
+    std::io::copy(&mut spool, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// Compresses `reader` in a single pass with adaptive Huffman codes, nothing is buffered beyond
+/// the current block.
+pub fn compress_adaptive<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+    let mut encoder = HuffmanEncoder::adaptive(writer);
+
+    std::io::copy(reader, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// Compresses `reader` with code tables conditioned on the preceding byte, which pays off on text
+/// and other data where neighbouring bytes are strongly correlated.
+pub fn compress_context<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+    let mut encoder = HuffmanEncoder::with_contexts(writer);
+
+    std::io::copy(reader, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// Compresses `reader` with a pre-trained `table`, which the output refers to by its ID only and
+/// which has to be passed to `decompress_with_table`.
+pub fn compress_with_table<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    table: &SharedTable,
+) -> Result<()> {
+    let mut encoder = HuffmanEncoder::with_shared_table(writer, table);
+
+    std::io::copy(reader, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// Compresses `reader` as it is read with Vitter's adaptive codes, flushing `writer` after every
+/// read so that the output keeps up with input arriving slowly, like a log being written or a
+/// socket. Nothing is buffered, counted upfront or sent as a code table.
+pub fn compress_live<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+    let mut encoder = LiveEncoder::new(writer)?;
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let read_len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read_len) => read_len,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        encoder.write_all(&buf[..read_len])?;
+        encoder.flush()?;
+    }
+
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// Reads a compressed stream from `reader` and writes the original bytes into `writer`.
+pub fn decompress<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+    let mut decoder = HuffmanDecoder::new(reader);
+
+    std::io::copy(&mut decoder, writer)?;
+
+    Ok(())
+}
+
+/// Same as `decompress`, for streams written by `compress_with_table` with the same `table`.
+pub fn decompress_with_table<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    table: &SharedTable,
+) -> Result<()> {
+    let mut decoder = HuffmanDecoder::with_shared_table(reader, table);
+
+    std::io::copy(&mut decoder, writer)?;
+
+    Ok(())
+}
+
+/// Same as `decompress`, but without verifying block checksums.
+pub fn decompress_unverified<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<()> {
+    let mut decoder = HuffmanDecoder::new(reader);
+    decoder.set_verify(false);
+
+    std::io::copy(&mut decoder, writer)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    fn datasets() -> Vec<Vec<u8>> {
+        let mut result = Vec::new();
+
+        for dataset in fs::read_dir("test-data").expect("Could not read test datasets") {
+            for file in fs::read_dir(dataset.unwrap().path()).unwrap() {
+                result.push(fs::read(file.unwrap().path()).unwrap());
+            }
+        }
+
+        result
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut messages = datasets();
+        messages.push(Vec::new());
+
+        for message in messages {
+            let mut compressed = Vec::new();
+            super::compress(&mut Cursor::new(&message), &mut compressed).unwrap();
+
+            let mut decompressed = Vec::new();
+            super::decompress(&mut compressed.as_slice(), &mut decompressed).unwrap();
+
+            assert_eq!(decompressed, message);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_block_sizes() {
+        let message: Vec<u8> = (0..300_000u32)
+            .map(|index| (index / 1000 % 16 * (index % 7)) as u8)
+            .collect();
+
+        for block_size in [64 * 1024, 100_000, 4 * 1024 * 1024] {
+            let mut compressed = Vec::new();
+            super::compress_with_block_size(&mut message.as_slice(), &mut compressed, block_size)
+                .unwrap();
+
+            assert_eq!(super::decode(&compressed).unwrap(), message);
+        }
+
+        for block_size in [0, 1024, 4 * 1024 * 1024 + 1] {
+            let err = super::compress_with_block_size(&mut &b""[..], &mut Vec::new(), block_size)
+                .err()
+                .unwrap();
+
+            assert!(matches!(err, super::Error::InvalidArgument(_)));
+        }
+    }
+
+    #[test]
+    fn test_round_trip_two_pass() {
+        for message in datasets() {
+            let mut compressed = Vec::new();
+            super::compress_two_pass(&mut message.as_slice(), &mut compressed).unwrap();
+
+            assert_eq!(super::decode(&compressed).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_adaptive() {
+        let mut messages = datasets();
+        messages.push(Vec::new());
+
+        for message in messages {
+            let mut compressed = Vec::new();
+            super::compress_adaptive(&mut message.as_slice(), &mut compressed).unwrap();
+
+            assert_eq!(super::decode(&compressed).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_live() {
+        let mut messages = datasets();
+        messages.push(Vec::new());
+        messages.push((0..200_000u32).map(|index| (index % 251) as u8).collect());
+
+        for message in messages {
+            let mut compressed = Vec::new();
+            super::compress_live(&mut message.as_slice(), &mut compressed).unwrap();
+
+            assert_eq!(super::decode(&compressed).unwrap(), message);
+        }
+
+        // no code table to pay for
+        let message = datasets().concat();
+        let mut compressed = Vec::new();
+        super::compress_live(&mut message.as_slice(), &mut compressed).unwrap();
+
+        assert!(compressed.len() < super::encode(&message).unwrap().len());
+    }
+
+    #[test]
+    fn test_round_trip_context() {
+        let mut messages = datasets();
+        messages.push(Vec::new());
+        messages.push(
+            b"the quick brown fox jumps over the lazy dog, then the dog jumps over the fox\n"
+                .repeat(4000),
+        );
+
+        for message in messages {
+            let mut compressed = Vec::new();
+            super::compress_context(&mut message.as_slice(), &mut compressed).unwrap();
+
+            assert_eq!(super::decode(&compressed).unwrap(), message);
+        }
+
+        // text has few distinct bytes after any given one
+        let message = datasets().concat().repeat(200);
+        let mut compressed = Vec::new();
+        super::compress_context(&mut message.as_slice(), &mut compressed).unwrap();
+
+        assert!(compressed.len() < super::encode(&message).unwrap().len());
+    }
+
+    #[test]
+    fn test_round_trip_shared_table() {
+        let mut frequencies = [0u64; 256];
+
+        let corpus = datasets().concat().repeat(20);
+        super::shared_table::count_frequencies(&mut Cursor::new(corpus), &mut frequencies).unwrap();
+
+        let table = super::SharedTable::from_frequencies(&frequencies).unwrap();
+
+        let mut messages = datasets();
+        messages.push(Vec::new());
+        messages.push((0..=255u8).collect());
+
+        for message in messages {
+            let mut compressed = Vec::new();
+            super::compress_with_table(&mut message.as_slice(), &mut compressed, &table).unwrap();
+
+            let mut decompressed = Vec::new();
+            super::decompress_with_table(&mut compressed.as_slice(), &mut decompressed, &table)
+                .unwrap();
+
+            assert_eq!(decompressed, message);
+        }
+
+        // small messages gain without carrying a table
+        for message in datasets() {
+            let mut compressed = Vec::new();
+            super::compress_with_table(&mut message.as_slice(), &mut compressed, &table).unwrap();
+
+            assert!(compressed.len() <= super::encode(&message).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        for message in datasets() {
+            let compressed = super::encode(&message).unwrap();
+
+            assert_eq!(super::decode(&compressed).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_every_byte() {
+        let message: Vec<u8> = (0..=255u8).chain((0..=255u8).rev()).collect();
+
+        assert_eq!(
+            super::decode(&super::encode(&message).unwrap()).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_encode_decode_skewed() {
+        let frequencies = super::compressor::tree::fibonacci_frequencies(20);
+        let message: Vec<u8> = (0..20u8)
+            .flat_map(|byte| {
+                std::iter::repeat_n(byte.wrapping_mul(151), frequencies[byte as usize] as usize)
+            })
+            .collect();
+
+        assert_eq!(
+            super::decode(&super::encode(&message).unwrap()).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_encode_decode_single_symbol() {
+        for message in [vec![7u8], vec![b'a'; 20], vec![0u8; 100_000]] {
+            assert_eq!(
+                super::decode(&super::encode(&message).unwrap()).unwrap(),
+                message
+            );
+
+            let mut compressed = Vec::new();
+            super::compress_two_pass(&mut message.as_slice(), &mut compressed).unwrap();
+            assert_eq!(super::decode(&compressed).unwrap(), message);
+
+            let mut compressed = Vec::new();
+            super::compress_adaptive(&mut message.as_slice(), &mut compressed).unwrap();
+            assert_eq!(super::decode(&compressed).unwrap(), message);
+        }
+
+        // a bit per byte
+        assert!(super::encode(&[0u8; 100_000]).unwrap().len() < 100_000 / 8 + 64);
+    }
+
+    #[test]
+    fn test_encode_decode_empty() {
+        let compressed = super::encode(b"").unwrap();
+
+        assert_eq!(compressed, b"HUFF\x06\x00".to_vec());
+        assert_eq!(super::decode(&compressed).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_encode_deterministic() {
+        let message = b"abracadabra, or abacadabra, dabracadabra".to_vec();
+        let compressed = super::encode(&message).unwrap();
+
+        for _ in 0..16 {
+            assert_eq!(super::encode(&message).unwrap(), compressed);
+        }
+    }
+
+    #[test]
+    fn test_decode_garbage() {
+        assert!(super::decode(b"not a compressed message").is_err());
+    }
+}
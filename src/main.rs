@@ -1,61 +1,62 @@
 use std::env;
-use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
-use std::path::Path;
-
-mod bin_operations;
-mod compressor;
-mod decompressor;
-mod huffman;
-mod run_config;
-mod stream_helpers;
-
-fn main() -> std::io::Result<()> {
-    let args = run_config::Config::new(env::args())?;
-
-    println!(
-        "Mode: {}\nInput: {}\nOutput{}\n",
-        args.mode, args.file_in, args.file_out
-    );
-
-    let file_in = File::open(Path::new(&args.file_in))?;
-    let mut reader = BufReader::new(file_in);
+use std::process::ExitCode;
 
-    let initial_tree = compressor::tree::init(&mut reader)?;
-    let huffman_tree = compressor::tree::build(initial_tree);
+mod commands;
+mod run_config;
 
-    if let Some(root_node) = huffman_tree {
-        let codes = compressor::calc_codes(&root_node);
-        let stream_len = stream_helpers::stream_length(&mut reader)?;
+const NAME: &str = "huffman-coding";
 
-        reader.seek(SeekFrom::Start(0))?;
+fn main() -> ExitCode {
+    let config = match run_config::Config::new(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!(
+                "{}: {}\nTry `{} --help` for more information.",
+                NAME, err, NAME
+            );
+            return ExitCode::from(2);
+        }
+    };
 
-        while stream_helpers::stream_current_position(&mut reader)? != stream_len {
-            let chunk_size = std::cmp::min(stream_len, 1024);
+    if config.command == run_config::Command::Help {
+        print!("{}", run_config::USAGE);
+        return ExitCode::SUCCESS;
+    }
 
-            let mut chunk = vec![0u8; chunk_size as usize];
-            reader.read_exact(&mut chunk)?;
+    if config.command == run_config::Command::Train {
+        return match commands::train(&config) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{}: {}", NAME, err);
+                ExitCode::FAILURE
+            }
+        };
+    }
 
-            let res = compressor::compress(&chunk, &codes);
-            println!(">> Compressed stream >>");
-            for entry in &res.0 {
-                print!("{:b} ", entry);
+    let table = match &config.table {
+        Some(path) => match commands::load_table(path) {
+            Ok(table) => Some(table),
+            Err(err) => {
+                eprintln!("{}: {}: {}", NAME, path, err);
+                return ExitCode::FAILURE;
             }
-            println!("\n<< Compressed stream <<");
+        },
+        None => None,
+    };
 
-            println!(">> Decompressed stream >>");
-            println!("{}", decompressor::decompress(&res, &root_node));
-            println!("<< Decompressed stream <<\n");
+    // like gzip, a failing file does not stop the others
+    let mut failed = false;
 
-            println!(
-                "Message size: {} Compressed size: {}",
-                chunk.len(),
-                res.0.len()
-            );
+    for file in &config.files {
+        if let Err(err) = commands::run(&config, table.as_ref(), file) {
+            eprintln!("{}: {}: {}", NAME, commands::display_name(file), err);
+            failed = true;
         }
-    } else {
-        println!("Empty tree.");
     }
 
-    Ok(())
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
 }
@@ -1,42 +1,297 @@
-use std::env;
-use std::io;
-use std::io::{Error, ErrorKind};
+use huffman_coding::compressor::stream::{MAX_BLOCK_SIZE, MIN_BLOCK_SIZE};
+use huffman_coding::{Error, Result};
 
-pub struct Config {
-    pub file_in: String,
-    pub file_out: String,
-    pub mode: String,
+pub const USAGE: &str = "\
+Usage: huffman-coding <command> [options] [file...]
+
+Commands:
+  compress      compress files, FILE becomes FILE.huff
+  decompress    decompress files, FILE.huff becomes FILE
+  test          check that compressed files decode correctly
+  info          describe the blocks of compressed files
+  bench         measure compression ratio and speed on files
+  train         build a code table from sample files for --table
+
+Without files or with `-`, standard input is read and results go to standard output.
+
+Options:
+  -c, --stdout          write to standard output and keep input files
+  -o, --output FILE     write to FILE and keep the input, only with a single input
+                        except for `train`
+  -f, --force           overwrite existing files and write compressed data to a terminal
+  -k, --keep            keep input files
+  -v, --verbose         report sizes and ratios, per block for `info`
+  -h, --help            print this help
+      --block-size N    bytes per block, K and M suffixes allowed (64K to 4M)
+      --two-pass        use a single code table built from the whole input
+      --adaptive        use adaptive codes in a single pass
+      --live            use adaptive codes and write out every read as it comes,
+                        to follow pipes and sockets
+      --context         use a code table per preceding byte where it pays off,
+                        `bench` reports the gain over a single table
+      --table FILE      code with a table made by `train` instead of one per block,
+                        for inputs too small to carry their own
+      --no-verify       do not check block checksums when decompressing
+";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Compress,
+    Decompress,
+    Test,
+    Info,
+    Bench,
+    Train,
+    Help,
 }
 
-impl Config {
-    pub fn new(mut args: env::Args) -> io::Result<Config> {
-        args.next(); //skip app name
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Coding {
+    Blocks(usize),
+    TwoPass,
+    Adaptive,
+    Context,
+    Live,
+}
 
-        let err_not_enough_params = "Not enough actual parameters";
+#[derive(Debug)]
+pub struct Config {
+    pub command: Command,
+    // `-` stands for standard input
+    pub files: Vec<String>,
+    pub output: Option<String>,
+    pub to_stdout: bool,
+    pub force: bool,
+    pub keep: bool,
+    pub verbosity: u8,
+    pub coding: Coding,
+    // a table file written by `train`, used instead of `coding`
+    pub table: Option<String>,
+    pub verify: bool,
+}
 
-        let mode = match args.next() {
-            Some(arg) => arg,
-            None => return Err(Error::new(ErrorKind::Other, err_not_enough_params)),
+impl Config {
+    // `args` must not include the application name.
+    pub fn new<I: Iterator<Item = String>>(mut args: I) -> Result<Config> {
+        let command = match args.next().as_deref() {
+            Some("compress") => Command::Compress,
+            Some("decompress") => Command::Decompress,
+            Some("test") => Command::Test,
+            Some("info") => Command::Info,
+            Some("bench") => Command::Bench,
+            Some("train") => Command::Train,
+            Some("-h") | Some("--help") | Some("help") => Command::Help,
+            Some(_) => return Err(Error::InvalidArgument("Unknown command")),
+            None => return Err(Error::InvalidArgument("No command given")),
         };
 
-        let file_in = match args.next() {
-            Some(arg) => arg,
-            None => return Err(Error::new(ErrorKind::Other, err_not_enough_params)),
+        let mut config = Config {
+            command,
+            files: Vec::new(),
+            output: None,
+            to_stdout: false,
+            force: false,
+            keep: false,
+            verbosity: 0,
+            coding: Coding::Blocks(huffman_coding::compressor::stream::DEFAULT_BLOCK_SIZE),
+            table: None,
+            verify: true,
         };
 
-        let file_out = match args.next() {
-            Some(arg) => arg,
-            None => return Err(Error::new(ErrorKind::Other, err_not_enough_params)),
-        };
+        let mut block_size = None;
+        let mut two_pass = false;
+        let mut adaptive = false;
+        let mut context = false;
+        let mut live = false;
+        let mut only_files = false;
+
+        while let Some(arg) = args.next() {
+            if only_files || arg == "-" || !arg.starts_with('-') {
+                config.files.push(arg);
+                continue;
+            }
+
+            match arg.as_str() {
+                "--" => only_files = true,
+                "--stdout" => config.to_stdout = true,
+                "--output" => config.output = Some(option_value(&mut args)?),
+                "--force" => config.force = true,
+                "--keep" => config.keep = true,
+                "--verbose" => config.verbosity += 1,
+                "--help" => config.command = Command::Help,
+                "--block-size" => block_size = Some(parse_size(&option_value(&mut args)?)?),
+                "--two-pass" => two_pass = true,
+                "--adaptive" => adaptive = true,
+                "--context" => context = true,
+                "--live" => live = true,
+                "--table" => config.table = Some(option_value(&mut args)?),
+                "--no-verify" => config.verify = false,
+                _ if arg.starts_with("--") => return Err(Error::InvalidArgument("Unknown option")),
+                // short flags can be grouped, as in `-kv`
+                _ => {
+                    for flag in arg.chars().skip(1) {
+                        match flag {
+                            'c' => config.to_stdout = true,
+                            'o' => config.output = Some(option_value(&mut args)?),
+                            'f' => config.force = true,
+                            'k' => config.keep = true,
+                            'v' => config.verbosity += 1,
+                            'h' => config.command = Command::Help,
+                            _ => return Err(Error::InvalidArgument("Unknown option")),
+                        }
+                    }
+                }
+            }
+        }
+
+        if config.command == Command::Help {
+            return Ok(config);
+        }
+
+        let coding_chosen =
+            (block_size, two_pass, adaptive, context, live) != (None, false, false, false, false);
+
+        config.coding =
+            match (block_size, two_pass, adaptive, context, live) {
+                (Some(block_size), false, false, false, false) => Coding::Blocks(block_size),
+                (None, false, false, false, false) => config.coding,
+                (None, true, false, false, false) => Coding::TwoPass,
+                (None, false, true, false, false) => Coding::Adaptive,
+                (None, false, false, true, false) => Coding::Context,
+                (None, false, false, false, true) => Coding::Live,
+                _ => return Err(Error::InvalidArgument(
+                    "--block-size, --two-pass, --adaptive, --context and --live exclude each other",
+                )),
+            };
+
+        if config.table.is_some() && (coding_chosen || config.command == Command::Train) {
+            return Err(Error::InvalidArgument(
+                "--table excludes the other coding options and is not used by train",
+            ));
+        }
 
-        if &mode != "compress" && &mode != "decompress" {
-            return Err(io::Error::new(ErrorKind::Other, "Unknown mode"));
+        // checking the checksums is all that test does
+        if !config.verify && config.command == Command::Test {
+            return Err(Error::InvalidArgument(
+                "--no-verify cannot be used with test",
+            ));
         }
 
-        Ok(Config {
-            file_in,
-            file_out,
-            mode,
-        })
+        if config.files.is_empty() {
+            config.files.push(String::from("-"));
+        }
+
+        // a table is trained on all inputs together
+        let single_output = config.command != Command::Train;
+
+        if config.output.is_some()
+            && (config.to_stdout || (single_output && config.files.len() > 1))
+        {
+            return Err(Error::InvalidArgument(
+                "--output takes a single input and excludes --stdout",
+            ));
+        }
+
+        Ok(config)
+    }
+}
+
+fn option_value<I: Iterator<Item = String>>(args: &mut I) -> Result<String> {
+    args.next()
+        .ok_or(Error::InvalidArgument("Option requires a value"))
+}
+
+fn parse_size(value: &str) -> Result<usize> {
+    let err_invalid_size = "Block size must be between 64K and 4M";
+
+    let (digits, unit) = match value.as_bytes().last() {
+        Some(b'K') | Some(b'k') => (&value[..value.len() - 1], 1024),
+        Some(b'M') | Some(b'm') => (&value[..value.len() - 1], 1024 * 1024),
+        _ => (value, 1),
+    };
+
+    let size = digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|size| size.checked_mul(unit))
+        .ok_or(Error::InvalidArgument(err_invalid_size))?;
+
+    if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&size) {
+        return Err(Error::InvalidArgument(err_invalid_size));
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::run_config::{Coding, Command, Config};
+    use huffman_coding::Error;
+
+    fn parse(args: &str) -> huffman_coding::Result<Config> {
+        Config::new(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = parse("compress -kv a.txt -fv -- -b.txt").unwrap();
+
+        assert_eq!(config.command, Command::Compress);
+        assert_eq!(config.files, vec!["a.txt", "-b.txt"]);
+        assert!(config.keep && config.force && !config.to_stdout);
+        assert_eq!(config.verbosity, 2);
+        assert_eq!(config.coding, Coding::Blocks(1024 * 1024));
+        assert!(config.verify);
+
+        let config = parse("decompress --no-verify -c").unwrap();
+
+        assert_eq!(config.command, Command::Decompress);
+        assert_eq!(config.files, vec!["-"]);
+        assert!(config.to_stdout && !config.verify);
+
+        let config = parse("compress --block-size 128K -o out.huff in.txt").unwrap();
+
+        assert_eq!(config.coding, Coding::Blocks(128 * 1024));
+        assert_eq!(config.output.as_deref(), Some("out.huff"));
+
+        assert_eq!(parse("bench --adaptive").unwrap().coding, Coding::Adaptive);
+        assert_eq!(parse("compress --live").unwrap().coding, Coding::Live);
+        assert_eq!(parse("compress --context").unwrap().coding, Coding::Context);
+        assert_eq!(parse("info --help").unwrap().command, Command::Help);
+
+        let config = parse("compress --table words.table -c").unwrap();
+        assert_eq!(config.table.as_deref(), Some("words.table"));
+
+        let config = parse("train -o words.table a.txt b.txt").unwrap();
+        assert_eq!(config.command, Command::Train);
+        assert_eq!(config.files, vec!["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for args in [
+            "",
+            "squeeze a.txt",
+            "compress -x",
+            "compress --fast",
+            "compress -o",
+            "compress --block-size 1K",
+            "compress --block-size lots",
+            "compress --two-pass --adaptive",
+            "compress --live --context",
+            "compress --context --block-size 64K",
+            "compress -o out.huff a.txt b.txt",
+            "compress -c -o out.huff a.txt",
+            "compress --table",
+            "compress --table words.table --adaptive",
+            "train --table words.table a.txt",
+            "test --no-verify a.txt.huff",
+        ] {
+            assert!(
+                matches!(parse(args), Err(Error::InvalidArgument(_))),
+                "{}",
+                args
+            );
+        }
     }
 }
@@ -0,0 +1,157 @@
+use crate::codebook::CodeBook;
+use crate::error::{Error, Result};
+use crate::{checksum, compressor, container};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"HUFT";
+pub const VERSION: u8 = 1;
+
+// A code table trained on a sample corpus and kept apart from the compressed data, which only
+// refers to it by `id`. Small messages are not worth a table of their own, a shared one is paid
+// for once.
+//
+// Layout of a table file (integers are little-endian):
+//   magic[4] | version u8 | id u32 | code lengths of all 256 symbols as in a Huffman block
+//
+// The ID is the CRC-32 of the encoded code lengths, so equal tables share their ID.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedTable {
+    pub id: u32,
+    pub code_book: CodeBook,
+}
+
+impl SharedTable {
+    // Every byte gets a code, including those missing from the corpus, so the table codes any
+    // message; bytes the corpus has frequently get the short ones.
+    pub fn from_frequencies(frequencies: &[u64; 256]) -> Result<SharedTable> {
+        let mut smoothed = [0u64; 256];
+
+        for (smoothed, frequency) in smoothed.iter_mut().zip(frequencies) {
+            *smoothed = frequency.saturating_add(1);
+        }
+
+        SharedTable::from_code_book(CodeBook::from_frequencies(&smoothed)?)
+    }
+
+    pub fn from_code_book(code_book: CodeBook) -> Result<SharedTable> {
+        Ok(SharedTable {
+            id: checksum::crc32(&encoded_lengths(code_book.code_lengths())?),
+            code_book,
+        })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&self.id.to_le_bytes())?;
+        writer.write_all(&encoded_lengths(self.code_book.code_lengths())?)?;
+
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<SharedTable> {
+        let mut header = [0u8; 9];
+        reader.read_exact(&mut header)?;
+
+        if header[..4] != MAGIC {
+            return Err(Error::InvalidHeader("Not a code table file"));
+        }
+
+        if header[4] != VERSION {
+            return Err(Error::UnsupportedVersion(header[4]));
+        }
+
+        let id = u32::from_le_bytes([header[5], header[6], header[7], header[8]]);
+        let code_book = CodeBook::from_lengths(container::read_code_lengths(reader)?)?;
+        let table = SharedTable::from_code_book(code_book)?;
+
+        if table.id != id {
+            return Err(Error::InvalidHeader("Code table does not match its ID"));
+        }
+
+        Ok(table)
+    }
+}
+
+// Adds the byte frequencies of `corpus` to `frequencies`, so a table can be trained on several
+// files.
+pub fn count_frequencies<R: Read>(corpus: &mut R, frequencies: &mut [u64; 256]) -> Result<()> {
+    compressor::histogram::count_reader(corpus, frequencies)?;
+
+    Ok(())
+}
+
+fn encoded_lengths(code_lengths: &HashMap<u8, u8>) -> Result<Vec<u8>> {
+    let mut encoded = Vec::new();
+    container::write_code_lengths(&mut encoded, code_lengths)?;
+
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::shared_table::{self, SharedTable};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_train() {
+        let mut frequencies = [0u64; 256];
+        shared_table::count_frequencies(&mut Cursor::new(b"hello"), &mut frequencies).unwrap();
+        shared_table::count_frequencies(&mut Cursor::new(b"world"), &mut frequencies).unwrap();
+
+        assert_eq!(frequencies[b'l' as usize], 3);
+        assert_eq!(frequencies.iter().sum::<u64>(), 10);
+
+        let table = SharedTable::from_frequencies(&frequencies).unwrap();
+
+        let code_lengths = table.code_book.code_lengths();
+
+        assert_eq!(code_lengths.len(), 256);
+        assert!(code_lengths[&b'l'] < code_lengths[&b'z']);
+        assert_eq!(SharedTable::from_frequencies(&frequencies).unwrap(), table);
+    }
+
+    #[test]
+    fn test_train_huge_counts() {
+        for frequency in [u64::MAX / 2, u64::MAX] {
+            let table = SharedTable::from_frequencies(&[frequency; 256]).unwrap();
+
+            assert!(table
+                .code_book
+                .code_lengths()
+                .values()
+                .all(|length| *length == 8));
+        }
+    }
+
+    #[test]
+    fn test_write_read() {
+        let table = SharedTable::from_frequencies(&[1u64; 256]).unwrap();
+        assert!(table
+            .code_book
+            .code_lengths()
+            .values()
+            .all(|length| *length == 8));
+
+        let mut written = Vec::new();
+        table.write(&mut written).unwrap();
+
+        assert_eq!(&written[..5], b"HUFT\x01");
+        assert_eq!(SharedTable::read(&mut written.as_slice()).unwrap(), table);
+
+        // a table changed after its ID was written
+        let last = written.len() - 1;
+        written[last] = 7;
+
+        assert!(matches!(
+            SharedTable::read(&mut written.as_slice()),
+            Err(Error::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            SharedTable::read(&mut &b"HUFF\x04\x00\x00\x00\x00"[..]),
+            Err(Error::InvalidHeader(_))
+        ));
+    }
+}
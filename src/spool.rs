@@ -0,0 +1,130 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const DEFAULT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+static SPOOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+enum Storage {
+    Memory(Cursor<Vec<u8>>),
+    File { file: File, path: PathBuf },
+}
+
+// A seekable copy of a source that can only be read once, such as stdin or a pipe. Inputs up to
+// the memory limit are kept in memory, bigger ones are moved to a temporary file which is
+// removed on drop.
+pub struct Spool {
+    storage: Storage,
+}
+
+impl Spool {
+    pub fn new<R: Read>(reader: &mut R) -> std::io::Result<Spool> {
+        Spool::with_memory_limit(reader, DEFAULT_MEMORY_LIMIT)
+    }
+
+    pub fn with_memory_limit<R: Read>(
+        reader: &mut R,
+        memory_limit: usize,
+    ) -> std::io::Result<Spool> {
+        let mut buffered = Vec::new();
+        reader
+            .by_ref()
+            .take(memory_limit as u64 + 1)
+            .read_to_end(&mut buffered)?;
+
+        if buffered.len() <= memory_limit {
+            return Ok(Spool {
+                storage: Storage::Memory(Cursor::new(buffered)),
+            });
+        }
+
+        let path = std::env::temp_dir().join(format!(
+            "huffman-spool-{}-{}",
+            std::process::id(),
+            SPOOL_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        // created before copying so that the file is removed on errors too
+        let mut spool = Spool {
+            storage: Storage::File { file, path },
+        };
+
+        if let Storage::File { file, .. } = &mut spool.storage {
+            file.write_all(&buffered)?;
+            std::io::copy(reader, file)?;
+            file.rewind()?;
+        }
+
+        Ok(spool)
+    }
+
+    pub fn is_in_memory(&self) -> bool {
+        matches!(self.storage, Storage::Memory(_))
+    }
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.storage {
+            Storage::Memory(cursor) => cursor.read(buf),
+            Storage::File { file, .. } => file.read(buf),
+        }
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match &mut self.storage {
+            Storage::Memory(cursor) => cursor.seek(pos),
+            Storage::File { file, .. } => file.seek(pos),
+        }
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Storage::File { path, .. } = &self.storage {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spool::Spool;
+    use std::io::{Read, Seek};
+
+    #[test]
+    fn test_in_memory() {
+        let mut spool = Spool::with_memory_limit(&mut &b"small input"[..], 11).unwrap();
+        assert!(spool.is_in_memory());
+
+        let mut read = String::new();
+        spool.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "small input");
+    }
+
+    #[test]
+    fn test_temporary_file() {
+        let message: Vec<u8> = (0..10_000u32).map(|index| index as u8).collect();
+
+        let mut spool = Spool::with_memory_limit(&mut message.as_slice(), 1000).unwrap();
+        assert!(!spool.is_in_memory());
+
+        for _ in 0..2 {
+            let mut read = Vec::new();
+            spool.read_to_end(&mut read).unwrap();
+            assert_eq!(read, message);
+
+            spool.rewind().unwrap();
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::io::{Seek, SeekFrom};
 
 pub fn stream_current_position<T: Seek>(stream: &mut T) -> std::io::Result<u64> {
-    stream.seek(SeekFrom::Current(0))
+    stream.stream_position()
 }
 
 pub fn stream_length<T: Seek>(stream: &mut T) -> std::io::Result<u64> {
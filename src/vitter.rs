@@ -0,0 +1,418 @@
+use crate::bit_writer::BitWriter;
+use crate::error::{Error, Result};
+use std::io::Write;
+
+const NO_NODE: usize = usize::MAX;
+
+#[derive(Copy, Clone)]
+struct VitterNode {
+    weight: u64,
+    parent: usize,
+    // `NO_NODE` for leaves
+    left: usize,
+    right: usize,
+    // `None` for internal nodes and the NYT leaf
+    symbol: Option<u16>,
+}
+
+impl VitterNode {
+    fn leaf(parent: usize, symbol: Option<u16>) -> VitterNode {
+        VitterNode {
+            weight: 0,
+            parent,
+            left: NO_NODE,
+            right: NO_NODE,
+            symbol,
+        }
+    }
+}
+
+// Vitter's adaptive Huffman coding (algorithm Λ): encoder and decoder update identical trees
+// after every symbol, so no code table has to be sent, and a symbol seen for the first time is
+// sent as the code of the NYT ("not yet transmitted") leaf followed by its raw bits. Nodes are kept ordered by weight and, among equal weights, leaves before internal
+// nodes; an updated node slides past its whole block instead of swapping with a single node,
+// which keeps the tree of minimal height and the codes close to the static Huffman ones.
+//
+// The alphabet is `0..symbol_count`, leaving room for control symbols next to the 256 bytes.
+#[derive(Clone)]
+pub struct VitterModel {
+    nodes: Vec<VitterNode>,
+    leaves: Vec<usize>,
+    nyt: usize,
+    symbol_bits: u8,
+    path: Vec<bool>,
+}
+
+impl VitterModel {
+    pub fn new(symbol_count: usize) -> VitterModel {
+        let node_count = 2 * symbol_count + 1;
+        let symbol_bits = (usize::BITS - symbol_count.saturating_sub(1).leading_zeros()).max(1);
+
+        VitterModel {
+            nodes: vec![VitterNode::leaf(NO_NODE, None); node_count],
+            leaves: vec![NO_NODE; symbol_count],
+            nyt: node_count - 1,
+            symbol_bits: symbol_bits as u8,
+            path: Vec::new(),
+        }
+    }
+
+    pub fn encode<W: Write>(&mut self, symbol: u16, writer: &mut BitWriter<W>) -> Result<()> {
+        let leaf = *self
+            .leaves
+            .get(symbol as usize)
+            .ok_or(Error::InvalidArgument("Symbol is outside of the alphabet"))?;
+
+        if leaf == NO_NODE {
+            self.write_path(self.nyt, writer)?;
+            writer.write_bits(symbol as u64, self.symbol_bits)?;
+        } else {
+            self.write_path(leaf, writer)?;
+        }
+
+        self.update(symbol);
+
+        Ok(())
+    }
+
+    // `next_bit` is asked for one bit at a time, so a decoder never reads past the code. `offset`
+    // is the position of the code in the payload, errors report where it turned out corrupt.
+    pub fn decode<F: FnMut() -> std::io::Result<bool>>(
+        &mut self,
+        mut offset: u64,
+        mut next_bit: F,
+    ) -> Result<u16> {
+        let mut next_bit = || {
+            offset += 1;
+            next_bit()
+        };
+        let mut node = self.root();
+
+        while !self.is_leaf(node) {
+            node = if next_bit()? {
+                self.nodes[node].right
+            } else {
+                self.nodes[node].left
+            };
+        }
+
+        let symbol = match self.nodes[node].symbol {
+            Some(symbol) => symbol,
+            None => {
+                let mut symbol = 0usize;
+
+                for _ in 0..self.symbol_bits {
+                    symbol = (symbol << 1) | next_bit()? as usize;
+                }
+
+                // a known symbol is never sent as new
+                if self.leaves.get(symbol) != Some(&NO_NODE) {
+                    return Err(Error::CorruptPayload { offset });
+                }
+
+                symbol as u16
+            }
+        };
+
+        self.update(symbol);
+
+        Ok(symbol)
+    }
+
+    fn root(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    fn is_leaf(&self, index: usize) -> bool {
+        self.nodes[index].left == NO_NODE
+    }
+
+    fn write_path<W: Write>(&mut self, node: usize, writer: &mut BitWriter<W>) -> Result<()> {
+        self.path.clear();
+
+        let mut current = node;
+
+        while current != self.root() {
+            let parent = self.nodes[current].parent;
+            self.path.push(self.nodes[parent].right == current);
+            current = parent;
+        }
+
+        for bit in self.path.iter().rev() {
+            writer.write_bits(*bit as u64, 1)?;
+        }
+
+        Ok(())
+    }
+
+    fn update(&mut self, symbol: u16) {
+        // a leaf whose parent has to be incremented first, or it would slide past its parent
+        let mut leaf_to_increment = None;
+        let mut node = self.leaves[symbol as usize];
+
+        if node == NO_NODE {
+            node = self.split_nyt(symbol);
+            leaf_to_increment = Some(symbol);
+        } else {
+            let leader = self.block_leader(node);
+
+            if leader != node {
+                self.swap(node, leader);
+                node = leader;
+            }
+
+            let parent = self.nodes[node].parent;
+
+            if parent != NO_NODE && self.nodes[parent].left == self.nyt {
+                leaf_to_increment = Some(symbol);
+                node = parent;
+            }
+        }
+
+        while node != NO_NODE {
+            node = self.slide_and_increment(node);
+        }
+
+        if let Some(symbol) = leaf_to_increment {
+            self.slide_and_increment(self.leaves[symbol as usize]);
+        }
+    }
+
+    // The NYT leaf becomes an internal node with a new NYT leaf on the left and the symbol on the
+    // right; returns the internal node.
+    fn split_nyt(&mut self, symbol: u16) -> usize {
+        let parent = self.nyt;
+        let leaf = parent - 1;
+        let nyt = parent - 2;
+
+        self.nodes[leaf] = VitterNode::leaf(parent, Some(symbol));
+        self.nodes[nyt] = VitterNode::leaf(parent, None);
+        self.nodes[parent].left = nyt;
+        self.nodes[parent].right = leaf;
+
+        self.leaves[symbol as usize] = leaf;
+        self.nyt = nyt;
+
+        parent
+    }
+
+    // The highest numbered node of the same weight and kind.
+    fn block_leader(&self, node: usize) -> usize {
+        let weight = self.nodes[node].weight;
+        let leaf = self.is_leaf(node);
+        let mut leader = node;
+
+        while leader < self.root()
+            && self.nodes[leader + 1].weight == weight
+            && self.is_leaf(leader + 1) == leaf
+        {
+            leader += 1;
+        }
+
+        leader
+    }
+
+    // Moves `node` past the block it has to precede once incremented: a leaf past the internal
+    // nodes of its weight, an internal node past the leaves one heavier. Returns the node to
+    // increment next.
+    fn slide_and_increment(&mut self, node: usize) -> usize {
+        let former_parent = self.nodes[node].parent;
+        let weight = self.nodes[node].weight;
+        let leaf = self.is_leaf(node);
+
+        let mut target = node;
+
+        while target < self.root() {
+            let next = &self.nodes[target + 1];
+            let passed = if leaf {
+                !self.is_leaf(target + 1) && next.weight == weight
+            } else {
+                self.is_leaf(target + 1) && next.weight == weight + 1
+            };
+
+            if !passed {
+                break;
+            }
+
+            target += 1;
+        }
+
+        if target != node {
+            let moving = self.nodes[node];
+
+            for index in node..target {
+                self.nodes[index] = VitterNode {
+                    parent: self.nodes[index].parent,
+                    ..self.nodes[index + 1]
+                };
+            }
+
+            self.nodes[target] = VitterNode {
+                parent: self.nodes[target].parent,
+                ..moving
+            };
+
+            for index in node..=target {
+                self.adopt(index);
+            }
+        }
+
+        self.nodes[target].weight += 1;
+
+        // the nodes sliding down hand their weight to the former parent of an internal node,
+        // a leaf adds its own to its new parent
+        if leaf {
+            self.nodes[target].parent
+        } else {
+            former_parent
+        }
+    }
+
+    // Exchanges two subtrees, each position keeps its parent.
+    fn swap(&mut self, first: usize, second: usize) {
+        let first_node = self.nodes[first];
+        let second_node = self.nodes[second];
+
+        self.nodes[first] = VitterNode {
+            parent: first_node.parent,
+            ..second_node
+        };
+        self.nodes[second] = VitterNode {
+            parent: second_node.parent,
+            ..first_node
+        };
+
+        self.adopt(first);
+        self.adopt(second);
+    }
+
+    fn adopt(&mut self, index: usize) {
+        let node = self.nodes[index];
+
+        if !self.is_leaf(index) {
+            self.nodes[node.left].parent = index;
+            self.nodes[node.right].parent = index;
+            return;
+        }
+
+        match node.symbol {
+            Some(symbol) => self.leaves[symbol as usize] = index,
+            None => self.nyt = index,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bit_reader::BitReader;
+    use crate::bit_writer::BitWriter;
+    use crate::error::Error;
+    use crate::vitter::{VitterModel, NO_NODE};
+
+    // Weights grow with the node number, leaves come before internal nodes of the same weight
+    // and every internal node weighs as much as its children.
+    fn check_invariants(model: &VitterModel) {
+        let used = &model.nodes[model.nyt..];
+
+        for (offset, pair) in used.windows(2).enumerate() {
+            let index = model.nyt + offset;
+
+            assert!(pair[0].weight <= pair[1].weight);
+
+            if pair[0].weight == pair[1].weight {
+                assert!(model.is_leaf(index) || !model.is_leaf(index + 1));
+            }
+        }
+
+        for index in model.nyt..model.nodes.len() {
+            let node = &model.nodes[index];
+
+            if node.left != NO_NODE {
+                assert_eq!(
+                    node.weight,
+                    model.nodes[node.left].weight + model.nodes[node.right].weight
+                );
+                assert_eq!(model.nodes[node.left].parent, index);
+                assert_eq!(model.nodes[node.right].parent, index);
+            }
+        }
+    }
+
+    fn round_trip(symbol_count: usize, message: &[u16]) -> u64 {
+        let mut encoder = VitterModel::new(symbol_count);
+        let mut writer = BitWriter::new(Vec::new());
+
+        for symbol in message {
+            encoder.encode(*symbol, &mut writer).unwrap();
+            check_invariants(&encoder);
+        }
+
+        let bit_len = writer.bit_len();
+        let compressed = writer.finish().unwrap();
+
+        let mut decoder = VitterModel::new(symbol_count);
+        let mut reader = BitReader::new(compressed.as_slice());
+
+        for symbol in message {
+            let offset = reader.bits_consumed();
+            assert_eq!(
+                decoder.decode(offset, || reader.read_bit()).unwrap(),
+                *symbol
+            );
+        }
+
+        assert_eq!(reader.bits_consumed(), bit_len);
+
+        bit_len
+    }
+
+    fn bytes(message: &[u8]) -> Vec<u16> {
+        message.iter().map(|byte| *byte as u16).collect()
+    }
+
+    #[test]
+    fn test_first_symbols() {
+        // "a" as its 9 raw bits, "b" after the NYT code "0", then "a" in a single bit
+        assert_eq!(round_trip(258, &bytes(b"aba")), 9 + 10 + 1);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let message = bytes(b"she sells sea shells by the sea shore");
+
+        assert!(round_trip(258, &message) < message.len() as u64 * 8);
+    }
+
+    #[test]
+    fn test_round_trip_every_symbol() {
+        let message: Vec<u16> = (0..258u16)
+            .chain((0..20_000u32).map(|index| (index * index % 257) as u16))
+            .collect();
+
+        round_trip(258, &message);
+    }
+
+    #[test]
+    fn test_close_to_static_huffman() {
+        let message = b"abracadabra".repeat(500);
+        let bit_len = round_trip(258, &bytes(&message));
+
+        // a: 5, b: 2, r: 2, c: 1, d: 1 per 11 bytes, 23 bits with static codes
+        assert!(bit_len < 500 * 23 + message.len() as u64);
+    }
+
+    #[test]
+    fn test_decode_invalid_symbol() {
+        // the NYT escape followed by the 9 bits of 300 in an alphabet of 258
+        let mut bits = (0..9).rev().map(|shift| (300u16 >> shift) & 1 == 1);
+
+        assert!(matches!(
+            VitterModel::new(258).decode(100, || Ok(bits.next().unwrap())),
+            Err(Error::CorruptPayload { offset: 109 })
+        ));
+        assert!(matches!(
+            VitterModel::new(258).encode(258, &mut BitWriter::new(Vec::new())),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+}